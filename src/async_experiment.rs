@@ -0,0 +1,536 @@
+use std::{
+    collections::HashMap,
+    error::Error as StdError,
+    future::Future,
+    pin::Pin,
+    time::Duration,
+};
+
+use serde::Serialize;
+
+use crate::{
+    context::Context,
+    errors::{BehaviorError, BehaviorNotUnique, ErrorAction, Operation, VictorsErrors, VictorsResult},
+    experiment::{Experiment, CONTROL_NAME, DEFAULT_CANDIDATE_NAME},
+    experiment_result::ExperimentResult,
+    observation::{Comparison, Observation},
+    result_publisher::Publisher,
+    sampling::Sampler,
+    timeout::Timeout,
+    unwind::CatchUnwind,
+};
+
+type BehaviorFuture<'a, R> = Pin<Box<dyn Future<Output = Result<R, BehaviorError>> + 'a>>;
+type BoxedBehavior<'a, R> = Box<dyn Fn() -> BehaviorFuture<'a, R> + 'a>;
+
+/// An experiment whose behaviors are asynchronous.
+///
+/// Behaviors return futures which are awaited one after another in a random order. The experiment
+/// does not depend on any particular async runtime. Apart from that it has the same result,
+/// ignore, comparator and publisher semantics as [Experiment].
+pub struct AsyncExperiment<'a, R: Serialize> {
+    experiment: Experiment<'a, R>,
+    behaviors: HashMap<String, BoxedBehavior<'a, R>>,
+}
+
+impl<'a, R: PartialEq + Serialize> Default for AsyncExperiment<'a, R> {
+    /// Creates a new experiment with the name "experiment"
+    fn default() -> Self {
+        return Self {
+            experiment: Experiment::default(),
+            behaviors: Default::default(),
+        };
+    }
+}
+
+impl<'a, R: PartialEq + Serialize> AsyncExperiment<'a, R> {
+    /// Creates a new experiment whose values are compared with `==`
+    ///
+    /// # Arguments
+    /// * `name` - the name of the experiment
+    pub fn new(name: &'static str) -> Self {
+        return Self {
+            experiment: Experiment::new(name),
+            behaviors: Default::default(),
+        };
+    }
+
+    /// Creates a new experiment with initial context
+    ///
+    /// # Arguments
+    /// * `name` - the name of the experiment
+    /// * `context` - Map of extra experiment data
+    pub fn new_with_context(name: &'static str, context: Context) -> Self {
+        return Self {
+            experiment: Experiment::new_with_context(name, context),
+            behaviors: Default::default(),
+        };
+    }
+}
+
+impl<'a, R: Serialize> AsyncExperiment<'a, R> {
+    /// Creates a new experiment whose values are compared with the given comparator.
+    /// See [Experiment::with_comparator]
+    pub fn with_comparator<F>(name: &'static str, comparator: F) -> Self
+    where
+        F: Fn(&R, &R) -> bool + 'a,
+    {
+        return Self {
+            experiment: Experiment::with_comparator(name, comparator),
+            behaviors: Default::default(),
+        };
+    }
+
+    /// Returns the name of the experiment
+    pub fn name(&self) -> &String {
+        return &self.experiment.name;
+    }
+
+    /// Returns reference to the experiment context
+    pub fn context(&self) -> &Context {
+        return &self.experiment.context;
+    }
+
+    /// Define a block that determines whether or not the candidate experiments should run.
+    pub fn run_if<F>(&mut self, block: F)
+    where
+        F: Fn() -> bool + 'a,
+    {
+        self.experiment.run_if(block)
+    }
+
+    /// Register a candidate behavior for this experiment, defaults name to "candidate".
+    pub fn candidate<F, Fut>(&mut self, f: F) -> VictorsResult<()>
+    where
+        F: Fn() -> Fut + 'a,
+        Fut: Future<Output = R> + 'a,
+    {
+        self.add_behavior(DEFAULT_CANDIDATE_NAME, boxed_infallible(f))
+    }
+
+    /// Register a named candidate behavior for this experiment.
+    pub fn candidate_with_name<F, Fut>(&mut self, name: &str, f: F) -> VictorsResult<()>
+    where
+        F: Fn() -> Fut + 'a,
+        Fut: Future<Output = R> + 'a,
+    {
+        self.add_behavior(name, boxed_infallible(f))
+    }
+
+    /// Register the control behavior for this experiment.
+    pub fn control<F, Fut>(&mut self, f: F) -> VictorsResult<()>
+    where
+        F: Fn() -> Fut + 'a,
+        Fut: Future<Output = R> + 'a,
+    {
+        self.add_behavior(CONTROL_NAME, boxed_infallible(f))
+    }
+
+    /// Register a fallible candidate behavior for this experiment, defaults name to "candidate".
+    /// See [Experiment::try_candidate]
+    pub fn try_candidate<F, Fut, E>(&mut self, f: F) -> VictorsResult<()>
+    where
+        F: Fn() -> Fut + 'a,
+        Fut: Future<Output = Result<R, E>> + 'a,
+        E: Into<BehaviorError>,
+    {
+        self.add_behavior(DEFAULT_CANDIDATE_NAME, boxed_fallible(f))
+    }
+
+    /// Register a named fallible candidate behavior for this experiment.
+    pub fn try_candidate_with_name<F, Fut, E>(&mut self, name: &str, f: F) -> VictorsResult<()>
+    where
+        F: Fn() -> Fut + 'a,
+        Fut: Future<Output = Result<R, E>> + 'a,
+        E: Into<BehaviorError>,
+    {
+        self.add_behavior(name, boxed_fallible(f))
+    }
+
+    /// Register a fallible control behavior for this experiment.
+    /// See [Experiment::try_control]
+    pub fn try_control<F, Fut, E>(&mut self, f: F) -> VictorsResult<()>
+    where
+        F: Fn() -> Fut + 'a,
+        Fut: Future<Output = Result<R, E>> + 'a,
+        E: Into<BehaviorError>,
+    {
+        self.add_behavior(CONTROL_NAME, boxed_fallible(f))
+    }
+
+    fn add_behavior(&mut self, name: &str, behavior: BoxedBehavior<'a, R>) -> VictorsResult<()> {
+        if self.behaviors.contains_key(name) {
+            return Err(VictorsErrors::BehaviorNotUnique(BehaviorNotUnique {
+                experiment_name: self.experiment.name.to_string(),
+                name: name.to_string(),
+            }));
+        }
+        self.behaviors.insert(name.to_string(), behavior);
+
+        return Ok(());
+    }
+
+    /// Define a block of code to run before an experiment begins, if the experiment is enabled.
+    pub fn before_run<F>(&mut self, f: F)
+    where
+        F: Fn() + 'a,
+    {
+        self.experiment.before_run(f)
+    }
+
+    /// A block to clean an observed value for publishing or storing.
+    /// See [Experiment::clean]
+    pub fn clean<F, C>(&mut self, f: F)
+    where
+        F: Fn(&R) -> C + 'a,
+        C: Serialize,
+    {
+        self.experiment.clean(f)
+    }
+
+    pub fn add_context(&mut self, context: Context) {
+        self.experiment.add_context(context)
+    }
+
+    /// Configure experiment to ignore observations based on the given block.
+    /// See [Experiment::add_ignore]
+    pub fn add_ignore<F>(&mut self, ignore_block: F)
+    where
+        F: Fn(&Observation<R>, &Observation<R>) -> bool + 'a,
+    {
+        self.experiment.add_ignore(ignore_block)
+    }
+
+    /// See [Experiment::ignore_mismatch_observation]
+    pub fn ignore_mismatch_observation(&self, control: &Observation<R>, candidate: &Observation<R>) -> bool {
+        self.experiment.ignore_mismatch_observation(control, candidate)
+    }
+
+    pub fn observations_are_equivalent(&self, a: &Observation<R>, b: &Observation<R>) -> bool {
+        return self.experiment.observations_are_equivalent(a, b);
+    }
+
+    pub fn enabled<F>(&mut self, enabled: F)
+    where
+        F: Fn() -> bool + 'a,
+    {
+        self.experiment.enabled(enabled)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        return self.experiment.is_enabled();
+    }
+
+    /// See [Experiment::sampler]
+    pub fn sampler<S: Sampler + 'a>(&mut self, sampler: S) {
+        self.experiment.sampler(sampler)
+    }
+
+    /// See [Experiment::percent_enabled]
+    pub fn percent_enabled(&mut self, percent: f64) {
+        self.experiment.percent_enabled(percent)
+    }
+
+    pub fn result_publisher<T: Publisher<R> + 'a>(&mut self, publisher: T) {
+        self.experiment.result_publisher(publisher)
+    }
+
+    /// Decide what happens when an operation of the experiment fails. See [Experiment::on_error]
+    pub fn on_error<F>(&mut self, handler: F)
+    where
+        F: Fn(Operation, &VictorsErrors) -> ErrorAction + 'a,
+    {
+        self.experiment.on_error(handler)
+    }
+
+    /// See [Experiment::comparator]
+    pub fn comparator<F>(&mut self, comparator: F)
+    where
+        F: Fn(&R, &R) -> bool + 'a,
+    {
+        self.experiment.comparator(comparator)
+    }
+
+    /// See [Experiment::explaining_comparator]
+    pub fn explaining_comparator<F>(&mut self, comparator: F)
+    where
+        F: Fn(&R, &R) -> Comparison + 'a,
+    {
+        self.experiment.explaining_comparator(comparator)
+    }
+
+    /// See [Experiment::error_comparator]
+    pub fn error_comparator<F>(&mut self, comparator: F)
+    where
+        F: Fn(&String, &String) -> bool + 'a,
+    {
+        self.experiment.error_comparator(comparator)
+    }
+
+    /// Whether to return an error when the control and candidate mismatch.
+    /// See [Experiment::err_on_mismatches]
+    pub fn err_on_mismatches(&mut self, err_on_mismatches: bool) {
+        self.experiment.err_on_mismatches(err_on_mismatches)
+    }
+
+    /// A block which builds the error returned when the observations mismatch.
+    /// See [Experiment::raise_with]
+    pub fn raise_with<F, E>(&mut self, block: F)
+    where
+        F: Fn(&ExperimentResult<R>) -> E + 'a,
+        E: StdError + Send + Sync + 'static,
+    {
+        self.experiment.raise_with(block)
+    }
+
+    /// Abandon any candidate that runs longer than `timeout`, unless it has a timeout of its own.
+    ///
+    /// Abandoned candidates are dropped without being polled again and are recorded as timed out
+    /// observations. Each timed candidate uses a timer thread so that no particular runtime is required.
+    pub fn timeout(&mut self, timeout: Duration) {
+        self.experiment.set_timeout(timeout)
+    }
+
+    /// Abandon the named candidate if it runs longer than `timeout`.
+    /// See [AsyncExperiment::timeout]
+    pub fn candidate_timeout(&mut self, name: &str, timeout: Duration) {
+        self.experiment.set_candidate_timeout(name, timeout)
+    }
+
+    /// Same as [AsyncExperiment::run] but returns the caller's own error type.
+    /// See [Experiment::run_with_error]
+    pub async fn run_with_error<E>(&mut self) -> Result<R, E>
+    where
+        E: From<VictorsErrors> + StdError + 'static,
+    {
+        return self.run().await.map_err(VictorsErrors::into_error);
+    }
+
+    /// Run all the behaviors for this experiment, observing each and publishing the results.
+    /// Return the result of the control
+    pub async fn run(&mut self) -> VictorsResult<R> {
+        let block = match self.behaviors.get(CONTROL_NAME) {
+            None => return Err(self.experiment.behavior_missing(CONTROL_NAME)),
+            Some(block) => block,
+        };
+
+        if !self.experiment.start_run(self.behaviors.len())? {
+            return block().await.map_err(|err| self.experiment.behavior_failed(CONTROL_NAME, err));
+        }
+
+        let experiment = &self.experiment;
+        let (result, control_error) = experiment
+            .run_behaviors(&self.behaviors, CONTROL_NAME, |key, behavior| {
+                let future = behavior();
+                // candidates are isolated so that a panicking candidate never takes the control down with it
+                let is_control = key == CONTROL_NAME;
+                let timeout = experiment.candidate_timeout(key);
+                return async move {
+                    if is_control {
+                        return Some(Ok(future.await));
+                    }
+                    return Timeout::new(CatchUnwind::new(future), timeout).await;
+                };
+            })
+            .await?;
+        return self.experiment.publish_result(CONTROL_NAME, result, control_error);
+    }
+}
+
+fn boxed_infallible<'a, R, F, Fut>(f: F) -> BoxedBehavior<'a, R>
+where
+    F: Fn() -> Fut + 'a,
+    Fut: Future<Output = R> + 'a,
+{
+    return Box::new(move || {
+        let future = f();
+        Box::pin(async move { Ok(future.await) })
+    });
+}
+
+fn boxed_fallible<'a, R, F, Fut, E>(f: F) -> BoxedBehavior<'a, R>
+where
+    F: Fn() -> Fut + 'a,
+    Fut: Future<Output = Result<R, E>> + 'a,
+    E: Into<BehaviorError>,
+{
+    return Box::new(move || {
+        let future = f();
+        Box::pin(async move { future.await.map_err(Into::into) })
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        future, thread,
+        time::{Duration, Instant},
+    };
+
+    use futures::executor::block_on;
+
+    use crate::{
+        errors::VictorsErrors,
+        observation::ObservationState,
+        result_publisher::InMemoryPublisher,
+        timeout,
+        victor::{Scientist, Victor},
+        AsyncExperiment, ExperimentResult,
+    };
+
+    #[test]
+    fn should_return_control_result() {
+        let mut experiment = AsyncExperiment::default();
+        experiment.control(|| async { "control" }).unwrap();
+        experiment.candidate(|| async { "candidate" }).unwrap();
+
+        let result = block_on(experiment.run()).unwrap();
+
+        assert_eq!("control", result);
+    }
+
+    #[test]
+    fn should_return_error_when_control_is_missing() {
+        let mut experiment = AsyncExperiment::default();
+        experiment.candidate(|| async { 1 }).unwrap();
+
+        let result = block_on(experiment.run());
+
+        assert!(matches!(result, Err(VictorsErrors::BehaviorMissing(_))));
+    }
+
+    #[test]
+    fn should_publish_observations_of_every_behavior() {
+        let r: RefCell<Option<ExperimentResult<u8>>> = RefCell::new(None);
+
+        let mut experiment = AsyncExperiment::default();
+        experiment.control(|| async { 1 }).unwrap();
+        experiment.candidate(|| async { 1 }).unwrap();
+        experiment.candidate_with_name("second", || async { 2 }).unwrap();
+        experiment.result_publisher(InMemoryPublisher::new(|result| {
+            r.replace(Some(result.clone()));
+        }));
+
+        block_on(experiment.run()).unwrap();
+
+        let result = r.take().unwrap();
+        assert!(result.has_mismatches());
+        assert_eq!("second", result.mismatched()[0].name);
+    }
+
+    #[test]
+    fn should_not_run_candidates_when_disabled() {
+        let called = RefCell::new(false);
+
+        let mut experiment = AsyncExperiment::default();
+        experiment.control(|| async { 1 }).unwrap();
+        experiment.candidate(|| async {
+            called.replace(true);
+            1
+        }).unwrap();
+        experiment.enabled(|| false);
+
+        assert_eq!(1, block_on(experiment.run()).unwrap());
+        assert!(!called.take());
+    }
+
+    #[test]
+    fn should_ignore_mismatches_and_use_comparator() {
+        let r: RefCell<Option<ExperimentResult<&str>>> = RefCell::new(None);
+
+        let mut experiment = AsyncExperiment::default();
+        experiment.control(|| async { "a" }).unwrap();
+        experiment.candidate(|| async { "A" }).unwrap();
+        experiment.candidate_with_name("ignored", || async { "b" }).unwrap();
+        experiment.comparator(|a, b| a.eq_ignore_ascii_case(b));
+        experiment.add_ignore(|_control, candidate| candidate.name == "ignored");
+        experiment.result_publisher(InMemoryPublisher::new(|result| {
+            r.replace(Some(result.clone()));
+        }));
+
+        block_on(experiment.run()).unwrap();
+
+        let result = r.take().unwrap();
+        assert!(!result.has_mismatches());
+        assert_eq!(1, result.ignored().len());
+    }
+
+    #[test]
+    fn should_swallow_candidate_errors_and_panics() {
+        let r: RefCell<Option<ExperimentResult<u8>>> = RefCell::new(None);
+
+        let mut experiment = AsyncExperiment::default();
+        experiment.control(|| async { 1 }).unwrap();
+        experiment.try_candidate(|| async { Err("candidate failed") }).unwrap();
+        experiment.candidate_with_name("panics", || async { panic!("candidate exploded") }).unwrap();
+        experiment.result_publisher(InMemoryPublisher::new(|result| {
+            r.replace(Some(result.clone()));
+        }));
+
+        assert_eq!(1, block_on(experiment.run()).unwrap());
+        assert_eq!(2, r.take().unwrap().mismatched().len());
+    }
+
+    #[test]
+    fn should_abandon_candidates_that_run_past_their_timeout() {
+        let r: RefCell<Option<ExperimentResult<u8>>> = RefCell::new(None);
+
+        let mut experiment = AsyncExperiment::default();
+        experiment.control(|| async { 1 }).unwrap();
+        experiment.candidate(|| async { 1 }).unwrap();
+        experiment.candidate_with_name("hangs", future::pending).unwrap();
+        experiment.candidate_timeout("hangs", Duration::from_millis(20));
+        experiment.result_publisher(InMemoryPublisher::new(|result| {
+            r.replace(Some(result.clone()));
+        }));
+
+        assert_eq!(1, block_on(experiment.run()).unwrap());
+
+        let result = r.take().unwrap();
+        assert_eq!(1, result.mismatched().len());
+        assert_eq!("hangs", result.mismatched()[0].name);
+        assert_eq!(ObservationState::TimedOut, result.mismatched()[0].state);
+    }
+
+    #[test]
+    fn should_not_leave_timer_threads_behind_for_candidates_finishing_in_time() {
+        for _ in 0..100 {
+            let mut experiment = AsyncExperiment::default();
+            experiment.control(|| async { 1 }).unwrap();
+            experiment.candidate_with_name("timed", || async { 1 }).unwrap();
+            experiment.candidate_timeout("timed", Duration::from_secs(60));
+
+            assert_eq!(1, block_on(experiment.run()).unwrap());
+        }
+
+        // timers of other tests running concurrently are short, so wait for those as well
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while timeout::live_timers() > 0 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(0, timeout::live_timers());
+    }
+
+    #[test]
+    fn should_return_error_returned_by_control() {
+        let mut experiment: AsyncExperiment<'_, u8> = AsyncExperiment::default();
+        experiment.try_control(|| async { Err("control failed") }).unwrap();
+        experiment.candidate(|| async { 1 }).unwrap();
+
+        let result = block_on(experiment.run());
+
+        assert!(matches!(result, Err(VictorsErrors::BehaviorFailed(_))));
+    }
+
+    #[test]
+    fn should_be_able_to_create_and_run_experiment_via_victor() {
+        let r = block_on(Victor::conduct_async("conduct async test", |experiment| {
+            experiment.control(|| async { 1 })?;
+            experiment.candidate(|| async { 2 })?;
+            Ok(())
+        }));
+
+        assert_eq!(Some(1), r.ok());
+    }
+}
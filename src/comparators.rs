@@ -0,0 +1,338 @@
+//! Tolerant comparisons for use in an experiment's comparator.
+//!
+//! Each comparison takes its configuration as arguments:
+//!
+//! ```
+//! use victors::{comparators::{self, Tolerance}, Experiment};
+//!
+//! let tolerance = Tolerance::absolute(1e-9);
+//! let mut experiment: Experiment<'_, f64> = Experiment::new("floats");
+//! experiment.comparator(move |a, b| comparators::float_eq(*a, *b, tolerance));
+//! ```
+use serde::Serialize;
+
+use crate::{
+    diff::{self, Difference},
+    observation::Comparison,
+};
+
+/// How far apart two floats may be and still compare equal, see [float_eq].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tolerance {
+    /// largest allowed absolute difference
+    pub absolute: f64,
+    /// largest allowed difference relative to the larger magnitude of the two values
+    pub relative: f64,
+}
+
+impl Tolerance {
+    /// Allows an absolute difference of up to `epsilon`, or a relative difference of up to
+    /// `relative`, whichever is more forgiving.
+    pub const fn new(epsilon: f64, relative: f64) -> Self {
+        return Self { absolute: epsilon, relative };
+    }
+
+    /// Allows an absolute difference of up to `epsilon`
+    pub const fn absolute(epsilon: f64) -> Self {
+        return Self::new(epsilon, 0.0);
+    }
+
+    /// Allows a difference of up to `relative` times the larger magnitude of the two values
+    pub const fn relative(relative: f64) -> Self {
+        return Self::new(0.0, relative);
+    }
+}
+
+/// Whether two floats are equal within the given tolerance.
+///
+/// NaN is never equal to anything, and infinities are only equal to themselves.
+///
+/// # Arguments
+/// * `a` - the control value
+/// * `b` - the candidate value
+/// * `tolerance` - how far apart the values may be
+pub fn float_eq(a: f64, b: f64, tolerance: Tolerance) -> bool {
+    if a == b {
+        return true;
+    }
+    if !a.is_finite() || !b.is_finite() {
+        return false;
+    }
+    let difference = (a - b).abs();
+    return difference <= tolerance.absolute || difference <= tolerance.relative * a.abs().max(b.abs());
+}
+
+/// Whether two collections contain the same items, in any order.
+///
+/// Items are matched one to one, so duplicates have to appear the same number of times in both.
+/// Works for sequences as well as the entries of maps, e.g. `unordered_eq(a.iter(), b.iter())`.
+/// Only needs `PartialEq` so compares in quadratic time.
+pub fn unordered_eq<I, J, T>(a: I, b: J) -> bool
+where
+    I: IntoIterator<Item = T>,
+    J: IntoIterator<Item = T>,
+    T: PartialEq,
+{
+    let mut unmatched: Vec<T> = b.into_iter().collect();
+    for item in a {
+        match unmatched.iter().position(|other| *other == item) {
+            Some(i) => {
+                unmatched.swap_remove(i);
+            }
+            None => return false,
+        }
+    }
+    return unmatched.is_empty();
+}
+
+/// Whether two values have the same JSON form, apart from the ignored paths.
+/// See [json_compare]
+pub fn json_eq<T: Serialize>(a: &T, b: &T, ignored_paths: &[&str]) -> bool {
+    return json_compare(a, b, ignored_paths).is_equivalent();
+}
+
+/// Compares the JSON forms of two values, apart from the ignored paths, explaining each
+/// difference. Use it with [crate::Experiment::explaining_comparator].
+///
+/// Ignored paths are JSON Pointers, e.g. `/meta/updated_at`. Everything under an ignored path is
+/// ignored as well, and a `*` token matches any single key or index, e.g. `/items/*/id`. Values
+/// that can not be serialized are different.
+///
+/// # Arguments
+/// * `a` - the control value
+/// * `b` - the candidate value
+/// * `ignored_paths` - JSON Pointers of the parts of the values to leave out of the comparison
+pub fn json_compare<T: Serialize>(a: &T, b: &T, ignored_paths: &[&str]) -> Comparison {
+    let (a, b) = match (serde_json::to_value(a), serde_json::to_value(b)) {
+        (Ok(a), Ok(b)) => (a, b),
+        (Err(e), _) | (_, Err(e)) => return Comparison::different(format!("failed to serialize: {}", e)),
+    };
+
+    let reasons: Vec<String> = diff::diff(&a, &b)
+        .iter()
+        .filter(|difference| !ignored_paths.iter().any(|ignored| is_under(difference.path(), ignored)))
+        .map(describe)
+        .collect();
+    return if reasons.is_empty() {
+        Comparison::Equivalent
+    } else {
+        Comparison::Different(reasons)
+    };
+}
+
+/// Whether the JSON Pointer `path` is `ignored` or below it
+fn is_under(path: &str, ignored: &str) -> bool {
+    let mut path = path.split('/');
+    for token in ignored.split('/') {
+        match path.next() {
+            Some(segment) if token == "*" || token == segment => {}
+            _ => return false,
+        }
+    }
+    return true;
+}
+
+fn describe(difference: &Difference) -> String {
+    return match difference {
+        Difference::Added { path, value } => format!("{} added: {}", path, value),
+        Difference::Removed { path, value } => format!("{} removed: {}", path, value),
+        Difference::Changed { path, from, to } => format!("{} changed: {} -> {}", path, from, to),
+    };
+}
+
+/// How strings are normalized before being compared, see [normalized_eq].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Normalization {
+    pub trim: bool,
+    pub ignore_case: bool,
+    pub collapse_whitespace: bool,
+}
+
+impl Normalization {
+    /// Compares strings as they are
+    pub const fn new() -> Self {
+        return Self {
+            trim: false,
+            ignore_case: false,
+            collapse_whitespace: false,
+        };
+    }
+
+    /// Ignore leading and trailing whitespace
+    pub const fn trim(mut self) -> Self {
+        self.trim = true;
+        return self;
+    }
+
+    /// Ignore differences in case
+    pub const fn ignore_case(mut self) -> Self {
+        self.ignore_case = true;
+        return self;
+    }
+
+    /// Treat every run of whitespace as a single space
+    pub const fn collapse_whitespace(mut self) -> Self {
+        self.collapse_whitespace = true;
+        return self;
+    }
+
+    /// Returns the normalized form of `value`
+    pub fn apply(&self, value: &str) -> String {
+        let mut value = if self.trim { value.trim() } else { value }.to_string();
+        if self.collapse_whitespace {
+            let mut collapsed = String::with_capacity(value.len());
+            let mut in_whitespace = false;
+            for c in value.chars() {
+                if !c.is_whitespace() {
+                    collapsed.push(c);
+                } else if !in_whitespace {
+                    collapsed.push(' ');
+                }
+                in_whitespace = c.is_whitespace();
+            }
+            value = collapsed;
+        }
+        if self.ignore_case {
+            value = value.to_lowercase();
+        }
+        return value;
+    }
+}
+
+/// Whether two strings are equal once normalized.
+///
+/// # Arguments
+/// * `a` - the control value
+/// * `b` - the candidate value
+/// * `normalization` - how to normalize the strings before comparing them
+pub fn normalized_eq(a: &str, b: &str, normalization: Normalization) -> bool {
+    return normalization.apply(a) == normalization.apply(b);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        collections::{BTreeMap, HashMap},
+    };
+
+    use serde::Serialize;
+
+    use super::{float_eq, json_compare, json_eq, normalized_eq, unordered_eq, Normalization, Tolerance};
+    use crate::{observation::Comparison, result_publisher::InMemoryPublisher, Experiment, ExperimentResult};
+
+    #[test]
+    fn should_compare_floats_within_absolute_tolerance() {
+        assert!(float_eq(0.1 + 0.2, 0.3, Tolerance::absolute(1e-9)));
+        assert!(float_eq(1.0, 1.5, Tolerance::absolute(0.5)));
+        assert!(!float_eq(1.0, 1.6, Tolerance::absolute(0.5)));
+    }
+
+    #[test]
+    fn should_compare_floats_within_relative_tolerance() {
+        assert!(float_eq(1_000_000.0, 1_000_001.0, Tolerance::relative(1e-6)));
+        assert!(!float_eq(1.0, 2.0, Tolerance::relative(1e-6)));
+        assert!(float_eq(0.0, 1e-12, Tolerance::new(1e-9, 1e-6)));
+    }
+
+    #[test]
+    fn should_not_consider_nan_equal() {
+        assert!(!float_eq(f64::NAN, f64::NAN, Tolerance::absolute(f64::MAX)));
+        assert!(float_eq(f64::INFINITY, f64::INFINITY, Tolerance::absolute(0.0)));
+        assert!(!float_eq(f64::INFINITY, f64::MAX, Tolerance::relative(1.0)));
+    }
+
+    #[test]
+    fn should_compare_sequences_ignoring_order() {
+        assert!(unordered_eq(&[1, 2, 2, 3], &[2, 3, 2, 1]));
+        assert!(!unordered_eq(&[1, 2, 2], &[1, 1, 2]));
+        assert!(!unordered_eq(&[1, 2], &[1, 2, 3]));
+        assert!(unordered_eq(Vec::<u8>::new(), vec![]));
+    }
+
+    #[test]
+    fn should_compare_map_entries_ignoring_order() {
+        let a: HashMap<_, _> = vec![("a", 1), ("b", 2)].into_iter().collect();
+        let b: BTreeMap<_, _> = vec![("b", 2), ("a", 1)].into_iter().collect();
+
+        assert!(unordered_eq(a.iter(), b.iter()));
+        assert!(unordered_eq(vec![("a", 1), ("b", 2)], vec![("b", 2), ("a", 1)]));
+        assert!(!unordered_eq(vec![("a", 1)], vec![("a", 2)]));
+    }
+
+    #[derive(Serialize)]
+    struct Order {
+        id: u32,
+        updated_at: &'static str,
+        items: Vec<Item>,
+    }
+
+    #[derive(Serialize)]
+    struct Item {
+        sku: &'static str,
+        row_id: u32,
+    }
+
+    fn order(updated_at: &'static str, row_id: u32, sku: &'static str) -> Order {
+        return Order {
+            id: 1,
+            updated_at,
+            items: vec![Item { sku: "a", row_id }, Item { sku, row_id: row_id + 1 }],
+        };
+    }
+
+    #[test]
+    fn should_compare_json_forms_ignoring_paths() {
+        let a = order("monday", 1, "b");
+        let b = order("tuesday", 10, "b");
+
+        assert!(!json_eq(&a, &b, &[]));
+        assert!(!json_eq(&a, &b, &["/updated_at"]));
+        assert!(json_eq(&a, &b, &["/updated_at", "/items/*/row_id"]));
+        assert!(json_eq(&a, &b, &["/updated_at", "/items"]));
+    }
+
+    #[test]
+    fn should_explain_json_differences() {
+        let a = order("monday", 1, "b");
+        let b = order("monday", 1, "c");
+
+        assert_eq!(
+            Comparison::Different(vec!["/items/1/sku changed: \"b\" -> \"c\"".to_string()]),
+            json_compare(&a, &b, &["/updated_at"])
+        );
+    }
+
+    #[test]
+    fn should_compare_normalized_strings() {
+        assert!(!normalized_eq(" a ", "a", Normalization::new()));
+        assert!(normalized_eq(" a ", "a", Normalization::new().trim()));
+        assert!(normalized_eq("Hello World", "hello world", Normalization::new().ignore_case()));
+        assert!(normalized_eq("a  b\n\tc", "a b c", Normalization::new().collapse_whitespace()));
+        assert!(!normalized_eq(" a b", "a b", Normalization::new().collapse_whitespace()));
+        assert!(normalized_eq(
+            "  Hello\n  World ",
+            "hello world",
+            Normalization::new().trim().ignore_case().collapse_whitespace()
+        ));
+    }
+
+    #[test]
+    fn should_plug_into_experiment_comparator() {
+        let r: RefCell<Option<ExperimentResult<String>>> = RefCell::new(None);
+
+        let mut experiment = Experiment::default();
+        experiment.control(|| "Hello  World".to_string()).unwrap();
+        experiment.candidate(|| "hello world ".to_string()).unwrap();
+        experiment.comparator(|a, b| {
+            normalized_eq(a, b, Normalization::new().trim().ignore_case().collapse_whitespace())
+        });
+        experiment.result_publisher(InMemoryPublisher::new(|result| {
+            r.replace(Some(result.clone()));
+        }));
+
+        experiment.run().unwrap();
+
+        assert!(r.take().unwrap().matched());
+    }
+}
@@ -0,0 +1,164 @@
+use serde::Serialize;
+use serde_json::Value;
+
+/// A single structural difference between two JSON values.
+///
+/// Paths are [JSON Pointers](https://datatracker.ietf.org/doc/html/rfc6901) into the values being
+/// compared, e.g. `/address/lines/0`. The empty path refers to the whole value.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Difference {
+    /// The candidate has a value at `path` the control does not
+    Added { path: String, value: Value },
+    /// The control has a value at `path` the candidate does not
+    Removed { path: String, value: Value },
+    /// The control and candidate have different values at `path`
+    Changed { path: String, from: Value, to: Value },
+}
+
+impl Difference {
+    /// Returns the JSON Pointer of the value that differs
+    pub fn path(&self) -> &str {
+        return match self {
+            Difference::Added { path, .. } => path,
+            Difference::Removed { path, .. } => path,
+            Difference::Changed { path, .. } => path,
+        };
+    }
+}
+
+/// Computes the structural differences needed to turn `from` into `to`.
+///
+/// Objects are compared key by key and arrays index by index, so an element inserted at the start
+/// of an array shows up as every following element having changed. Any other pair of values that
+/// are not equal is reported as changed at their path. Returns an empty vec when the values are
+/// equal.
+///
+/// # Arguments
+/// * `from` - the value to compare against, usually the control's
+/// * `to` - the value compared, usually a candidate's
+pub fn diff(from: &Value, to: &Value) -> Vec<Difference> {
+    let mut differences = vec![];
+    diff_at(&mut String::new(), from, to, &mut differences);
+    return differences;
+}
+
+fn diff_at(path: &mut String, from: &Value, to: &Value, differences: &mut Vec<Difference>) {
+    match (from, to) {
+        (Value::Object(from), Value::Object(to)) => {
+            for (key, from_value) in from {
+                let len = path.len();
+                push_segment(path, key);
+                match to.get(key) {
+                    Some(to_value) => diff_at(path, from_value, to_value, differences),
+                    None => differences.push(Difference::Removed { path: path.clone(), value: from_value.clone() }),
+                }
+                path.truncate(len);
+            }
+            for (key, to_value) in to {
+                if !from.contains_key(key) {
+                    let len = path.len();
+                    push_segment(path, key);
+                    differences.push(Difference::Added { path: path.clone(), value: to_value.clone() });
+                    path.truncate(len);
+                }
+            }
+        }
+        (Value::Array(from), Value::Array(to)) => {
+            for i in 0..from.len().max(to.len()) {
+                let len = path.len();
+                push_segment(path, &i.to_string());
+                match (from.get(i), to.get(i)) {
+                    (Some(from_value), Some(to_value)) => diff_at(path, from_value, to_value, differences),
+                    (Some(from_value), None) => {
+                        differences.push(Difference::Removed { path: path.clone(), value: from_value.clone() })
+                    }
+                    (None, Some(to_value)) => {
+                        differences.push(Difference::Added { path: path.clone(), value: to_value.clone() })
+                    }
+                    (None, None) => {}
+                }
+                path.truncate(len);
+            }
+        }
+        (from, to) => {
+            if from != to {
+                differences.push(Difference::Changed { path: path.clone(), from: from.clone(), to: to.clone() });
+            }
+        }
+    }
+}
+
+/// Appends a reference token to a JSON Pointer, escaping `~` and `/` as RFC 6901 requires
+fn push_segment(path: &mut String, segment: &str) {
+    path.push('/');
+    path.push_str(&segment.replace('~', "~0").replace('/', "~1"));
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::{diff, Difference};
+
+    #[test]
+    fn should_return_no_differences_for_equal_values() {
+        let value = json!({"a": [1, {"b": null}], "c": "d"});
+
+        assert!(diff(&value, &value.clone()).is_empty());
+    }
+
+    #[test]
+    fn should_report_changed_scalars_at_the_root() {
+        assert_eq!(
+            vec![Difference::Changed { path: "".to_string(), from: json!(1), to: json!("1") }],
+            diff(&json!(1), &json!("1"))
+        );
+    }
+
+    #[test]
+    fn should_report_added_removed_and_changed_object_fields() {
+        let differences = diff(
+            &json!({"name": "a", "address": {"city": "x", "zip": "1"}}),
+            &json!({"name": "a", "address": {"city": "y"}, "age": 3}),
+        );
+
+        assert_eq!(
+            vec![
+                Difference::Changed { path: "/address/city".to_string(), from: json!("x"), to: json!("y") },
+                Difference::Removed { path: "/address/zip".to_string(), value: json!("1") },
+                Difference::Added { path: "/age".to_string(), value: json!(3) },
+            ],
+            differences
+        );
+    }
+
+    #[test]
+    fn should_compare_arrays_by_index() {
+        let differences = diff(&json!([1, 2, 3]), &json!([1, 4]));
+
+        assert_eq!(
+            vec![
+                Difference::Changed { path: "/1".to_string(), from: json!(2), to: json!(4) },
+                Difference::Removed { path: "/2".to_string(), value: json!(3) },
+            ],
+            differences
+        );
+    }
+
+    #[test]
+    fn should_escape_json_pointer_tokens() {
+        let differences = diff(&json!({"a/b": {"c~d": 1}}), &json!({"a/b": {"c~d": 2}}));
+
+        assert_eq!("/a~1b/c~0d", differences[0].path());
+    }
+
+    #[test]
+    fn should_serialize_differences_tagged_by_op() {
+        let difference = Difference::Removed { path: "/a".to_string(), value: json!(1) };
+
+        assert_eq!(
+            json!({"op": "removed", "path": "/a", "value": 1}),
+            serde_json::to_value(difference).unwrap()
+        );
+    }
+}
@@ -1,9 +1,78 @@
-use std::backtrace::Backtrace;
+use std::{backtrace::Backtrace, error::Error as StdError, fmt};
 
+use serde::Serialize;
 use thiserror::Error;
 
+use crate::{
+    experiment_result::ExperimentResult,
+    observation::{Observation, ObservationState},
+    result_publisher::ScientistValue,
+};
+
 pub type VictorsResult<T> = Result<T, VictorsErrors>;
 
+/// Type-erased error returned by a fallible behavior.
+pub type BehaviorError = Box<dyn StdError + Send + Sync + 'static>;
+
+/// Type-erased error returned by a [crate::Publisher] that failed to publish a result.
+pub type PublishError = Box<dyn StdError + Send + Sync + 'static>;
+
+/// Type-erased error built by a custom mismatch error block, see [crate::Experiment::raise_with].
+pub type CustomMismatchError = Box<dyn StdError + Send + Sync + 'static>;
+
+/// Operations of an experiment whose errors are handed to its error handler.
+/// See [crate::Experiment::on_error]
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operation {
+    /// Publishing the experiment result
+    Publish,
+    /// Comparing the values of two observations, see [crate::Experiment::comparator]
+    Compare,
+    /// Comparing the errors of two observations, see [crate::Experiment::error_comparator]
+    ErrorCompare,
+    /// Deciding whether to ignore a mismatch, see [crate::Experiment::add_ignore]
+    Ignore,
+    /// See [crate::Experiment::run_if]
+    RunIf,
+    /// See [crate::Experiment::enabled]
+    Enabled,
+    /// See [crate::Experiment::before_run]
+    BeforeRun,
+    /// Cleaning an observed value, see [crate::Experiment::clean]
+    Clean,
+    /// Deciding whether to run the candidates, see [crate::Experiment::sampler]
+    Sample,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Operation::Publish => "publish",
+            Operation::Compare => "compare",
+            Operation::ErrorCompare => "error_compare",
+            Operation::Ignore => "ignore",
+            Operation::RunIf => "run_if",
+            Operation::Enabled => "enabled",
+            Operation::BeforeRun => "before_run",
+            Operation::Clean => "clean",
+            Operation::Sample => "sample",
+        };
+        return f.write_str(name);
+    }
+}
+
+/// What to do with an error raised while running an operation of an experiment.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorAction {
+    /// Log the error and carry on
+    Log,
+    /// Ignore the error and carry on
+    Swallow,
+    /// Return the error from the experiment's `run`
+    Raise,
+}
+
 // TODO: not sure I like these structs but maybe should just go the tuple struct route
 
 #[derive(Debug)]
@@ -25,14 +94,101 @@ pub struct BehaviorNotUnique {
     pub name: String,
 }
 
+#[derive(Debug)]
+pub struct BehaviorFailed {
+    pub experiment_name: String,
+    pub name: String,
+    /// The error returned by the behavior
+    pub source: BehaviorError,
+}
+
+#[derive(Debug)]
+pub struct PublishFailed {
+    pub experiment_name: String,
+    /// The error returned by the publisher
+    pub source: PublishError,
+}
+
+#[derive(Debug)]
+pub struct OperationFailed {
+    pub experiment_name: String,
+    pub operation: Operation,
+    /// message of the panic raised by the block
+    pub message: String,
+    /// backtrace of the panic when backtraces are enabled
+    pub backtrace: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct CustomMismatch {
+    pub experiment_name: String,
+    /// The error built by the experiment's custom mismatch error block
+    pub source: CustomMismatchError,
+}
+
+/// Returned from `run` when the observations mismatched and the experiment errs on mismatches.
+///
+/// Renders the control and mismatched candidates, using their cleaned values where a clean block
+/// is configured and followed by the reasons given by the comparator, when displayed.
+/// Raw values of cleaned observations are not kept.
 #[derive(Debug)]
 pub struct MismatchError {
-    // ExperimentResult
     pub experiment_name: String,
-    pub exception: Option<String>, // the associated class....not sure we need this,
-    pub message: String,           // TODO: probably dont need this.
-    pub backtrace: Option<Backtrace>,
+    /// The result of the experiment run. Observations that have a cleaned value have no raw value
+    pub result: ExperimentResult<ScientistValue>,
+    /// Where the error was raised, captured when backtraces are enabled, see [Backtrace::capture]
+    pub backtrace: Backtrace,
+}
+
+impl MismatchError {
+    /// Creates an error for the mismatched experiment result
+    pub fn new<R: Serialize>(result: &ExperimentResult<R>) -> Self {
+        return Self {
+            experiment_name: result.experiment_name().to_string(),
+            result: result.erase().without_cleaned_raw_values(),
+            backtrace: Backtrace::capture(),
+        };
+    }
+
+    fn fmt_observation(f: &mut fmt::Formatter<'_>, observation: &Observation<ScientistValue>) -> fmt::Result {
+        writeln!(f, "{}:", observation.name)?;
+        match observation.state {
+            ObservationState::Panicked => {
+                writeln!(f, "  panicked: {}", observation.exception.as_deref().unwrap_or_default())?;
+                if let Some(backtrace) = &observation.backtrace {
+                    for line in backtrace.lines() {
+                        writeln!(f, "    {}", line)?;
+                    }
+                }
+            }
+            ObservationState::TimedOut => writeln!(f, "  timed out")?,
+            ObservationState::Completed => match (&observation.exception, &observation.cleaned_value, &observation.value) {
+                (Some(exception), _, _) => writeln!(f, "  error: {}", exception)?,
+                (None, Some(cleaned_value), _) => writeln!(f, "  {}", cleaned_value)?,
+                (None, None, Some(value)) => {
+                    writeln!(f, "  {}", serde_json::to_string(value).map_err(|_| fmt::Error)?)?
+                }
+                (None, None, None) => writeln!(f, "  <unserializable value>")?,
+            },
+        }
+        for reason in &observation.mismatch_reasons {
+            writeln!(f, "  reason: {}", reason)?;
+        }
+        return Ok(());
+    }
+}
 
+impl fmt::Display for MismatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "experiment '{}' observations mismatched:", self.experiment_name)?;
+        if let Some(control) = self.result.control() {
+            MismatchError::fmt_observation(f, control)?;
+        }
+        for candidate in self.result.mismatched() {
+            MismatchError::fmt_observation(f, candidate)?;
+        }
+        return Ok(());
+    }
 }
 
 #[non_exhaustive]
@@ -49,22 +205,71 @@ pub enum VictorsErrors {
     #[error("{} already has {} behavior", .0.experiment_name, .0.name)]
     BehaviorNotUnique(BehaviorNotUnique),
 
+    /// The behavior whose value is returned from the experiment returned an error
+    #[error("{} {} behavior failed: {}", .0.experiment_name, .0.name, .0.source)]
+    BehaviorFailed(BehaviorFailed),
+
+    /// The publisher failed to publish the experiment result
+    #[error("{} failed to publish result: {}", .0.experiment_name, .0.source)]
+    PublishFailed(PublishFailed),
+
+    /// A user supplied block panicked
+    #[error("{} {} block panicked: {}", .0.experiment_name, .0.operation, .0.message)]
+    OperationFailed(OperationFailed),
+
     /// An error happened while serializing JSON
     #[error(transparent)]
     Json(#[from] serde_json::Error),
 
-    #[error("experiment '{}' observations mismatched", .0.experiment_name)]
-    MismatchError(MismatchError),
+    /// The observations mismatched and the experiment errs on mismatches. Boxed as it is much larger
+    /// than the other variants
+    #[error("{0}")]
+    MismatchError(Box<MismatchError>),
+
+    /// The observations mismatched and the experiment was configured to build its own error.
+    /// See [crate::Experiment::raise_with]
+    #[error("{}", .0.source)]
+    CustomMismatch(CustomMismatch),
 
     /// Generic error
     #[error("{0}")]
     Msg(String),
 
-    // TODO: see if this is needed. looks like scientist doesnt use it anywhere
+    /// The behavior whose value is returned from the experiment has no value to return
     #[error("{0} didn't return a value")]
     NoValue(String),
 }
 
+impl VictorsErrors {
+    /// Convert into the caller's own error type.
+    ///
+    /// Custom mismatch errors, and errors returned by the control behavior, that are already of
+    /// type `E` are returned as they are. Every other error is converted with `E::from`.
+    pub fn into_error<E>(self) -> E
+    where
+        E: From<VictorsErrors> + StdError + 'static,
+    {
+        return match self {
+            VictorsErrors::CustomMismatch(mismatch) => match mismatch.source.downcast::<E>() {
+                Ok(error) => *error,
+                Err(source) => E::from(VictorsErrors::CustomMismatch(CustomMismatch {
+                    experiment_name: mismatch.experiment_name,
+                    source,
+                })),
+            },
+            VictorsErrors::BehaviorFailed(failed) => match failed.source.downcast::<E>() {
+                Ok(error) => *error,
+                Err(source) => E::from(VictorsErrors::BehaviorFailed(BehaviorFailed {
+                    experiment_name: failed.experiment_name,
+                    name: failed.name,
+                    source,
+                })),
+            },
+            error => E::from(error),
+        };
+    }
+}
+
 #[rustfmt::skip]
 impl PartialEq for VictorsErrors {
     fn eq(&self, other: &Self) -> bool {
@@ -78,10 +283,22 @@ impl PartialEq for VictorsErrors {
             (&VictorsErrors::BehaviorNotUnique(ref a), &VictorsErrors::BehaviorNotUnique(ref b)) => {
                 a.experiment_name == b.experiment_name && a.name == b.name
             }
-            (&VictorsErrors::MismatchError(ref a), &VictorsErrors::MismatchError(ref b)) => {
+            (&VictorsErrors::BehaviorFailed(ref a), &VictorsErrors::BehaviorFailed(ref b)) => {
                 a.experiment_name == b.experiment_name
-                    && a.experiment_name == b.experiment_name
-                    && a.message == b.message
+                    && a.name == b.name
+                    && a.source.to_string() == b.source.to_string()
+            }
+            (&VictorsErrors::PublishFailed(ref a), &VictorsErrors::PublishFailed(ref b)) => {
+                a.experiment_name == b.experiment_name && a.source.to_string() == b.source.to_string()
+            }
+            (&VictorsErrors::OperationFailed(ref a), &VictorsErrors::OperationFailed(ref b)) => {
+                a.experiment_name == b.experiment_name && a.operation == b.operation && a.message == b.message
+            }
+            (&VictorsErrors::MismatchError(ref a), &VictorsErrors::MismatchError(ref b)) => {
+                a.experiment_name == b.experiment_name && a.result == b.result
+            }
+            (&VictorsErrors::CustomMismatch(ref a), &VictorsErrors::CustomMismatch(ref b)) => {
+                a.experiment_name == b.experiment_name && a.source.to_string() == b.source.to_string()
             }
             (&VictorsErrors::NoValue(ref a), &VictorsErrors::NoValue(ref b)) => a == b,
             _ => false,
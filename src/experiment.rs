@@ -1,75 +1,36 @@
-use std::{collections::HashMap, time::Instant};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    convert::Infallible,
+    error::Error as StdError,
+    future::{self, Future},
+    ptr,
+    task::{self, Poll, RawWaker, RawWakerVTable, Waker},
+    time::{Duration, Instant},
+};
 
 use rand::{seq::SliceRandom, thread_rng};
 use serde::Serialize;
+use serde_json::Value;
 
 use crate::{
     context::Context,
-    errors::{BehaviorMissing, BehaviorNotUnique, MismatchError, VictorsErrors, VictorsResult},
+    errors::{
+        BehaviorError, BehaviorFailed, BehaviorMissing, BehaviorNotUnique, CustomMismatch, CustomMismatchError,
+        ErrorAction, MismatchError, Operation, OperationFailed, PublishFailed, VictorsErrors, VictorsResult,
+    },
     experiment_result::ExperimentResult,
-    observation::Observation,
-    result_publisher::{NoopPublisher, Publisher},
+    observation::{Comparison, Observation},
+    result_publisher::Publisher,
+    sampling::{PercentSampler, Sampler},
+    unwind::{self, CaughtPanic},
+    victor,
 };
 
-const CONTROL_NAME: &str = "control";
-const DEFAULT_CANDIDATE_NAME: &str = "candidate";
+pub(crate) const CONTROL_NAME: &str = "control";
+pub(crate) const DEFAULT_CANDIDATE_NAME: &str = "candidate";
 const DEFAULT_EXPERIMENT_NAME: &str = "experiment";
 
-pub trait Experimentation {
-    type Result;
-
-    // type T: Display = String;
-    type Ignore: FnOnce(&Observation<Self::Result>, &Observation<Self::Result>) -> bool;
-
-    type EnabledFn: Fn() -> bool;
-
-    type BeforeRunFn: Fn();
-
-    type RunIfFn: FnOnce() -> bool;
-
-    type CleanerFn: Fn(Self::Result);
-
-    type ComparatorFn: Fn(&Self::Result, &Self::Result) -> bool;
-
-    type ErrorComparatorFn: Fn(&String, &String) -> bool;
-
-    fn run(&self, name: Option<&str>) -> Self::Result;
-
-    fn enabled<F>(&mut self, enabled: F)
-    where
-        F: Fn() -> bool;
-
-    fn is_enabled(&self) -> bool;
-
-    fn before_run_block<F>(&mut self, block: F)
-    where
-        F: Fn();
-
-    fn run_if<F>(&mut self, block: F)
-    where
-        F: FnOnce() -> bool;
-
-    fn add_ignore<F>(&mut self, block: F)
-    where
-        F: Fn(&Observation<Self::Result>, &Observation<Self::Result>) -> bool;
-
-    fn cleaner<F>(&mut self, block: F)
-    where
-        F: Fn(Self::Result);
-
-    // fn publisher<T: Publisher<R> + 'a>(&mut self, publisher: T);
-
-    fn comparator<F>(&mut self, block: F)
-    where
-        F: Fn(&Self::Result, &Self::Result) -> bool;
-
-    fn error_comparator<F>(&mut self, block: F)
-    where
-        F: Fn(&String, &String) -> bool;
-
-    fn add_context(&mut self, context: Context);
-}
-
 // pub trait Experiment {
 //     // TODO: allow sampling which can be done see ramping up
 //     fn is_enabled(&self) -> bool;
@@ -84,27 +45,21 @@ pub trait Experimentation {
 // TODO: do we want to rename Experiment to ControlledExperiment
 // Then make Experiment a trait (or Experimentation).
 
-// TODO: make type alias for these various fn blocks
-// TODO:
-type BehaviorBlock<R> = fn() -> R;
-// type BehaviorBlock<R> = dyn FnOnce() -> R;
-type BoxedBehaviorBlock<R> = Box<BehaviorBlock<R>>;
-type RunIfBlock = fn() -> bool;
-type BeforeRunBlock = fn();
-type CleanerBlock<R> = fn(R);
-type EnabledFn = fn() -> bool;
-// type IgnoresBlock<R> = Box<dyn FnOnce(&Observation<R>, &Observation<R>) -> bool>;
-type IgnoresBlock<R> = fn(&Observation<R>, &Observation<R>) -> bool;
-type ValueComparator<R> = fn(a: &R, b: &R) -> bool;
-type ErrorComparator = fn(a: &String, b: &String) -> bool;
+type CleanerBlock<'a, R> = Box<dyn Fn(&R) -> VictorsResult<Value> + 'a>;
+type EnabledBlock<'a> = Box<dyn Fn() -> bool + 'a>;
+type ValueComparator<'a, R> = Box<dyn Fn(&R, &R) -> Comparison + 'a>;
+type ErrorComparator<'a> = Box<dyn Fn(&String, &String) -> bool + 'a>;
+type ErrorHandler<'a> = Box<dyn Fn(Operation, &VictorsErrors) -> ErrorAction + 'a>;
+type MismatchErrorBlock<'a, R> = Box<dyn Fn(&ExperimentResult<R>) -> CustomMismatchError + 'a>;
+/// The value or error a behavior returned, or the panic it raised
+pub(crate) type BehaviorOutcome<R> = Result<Result<R, BehaviorError>, CaughtPanic>;
 // type PublisherBlock<R> = Box<dyn Publisher<ExperimentResult<R>>>;
 // type PublisherBlock<R> = fn(result: &ExperimentResult<R>);
 
-pub struct Experiment<'a, R: Clone + PartialEq + Serialize> {
+pub struct Experiment<'a, R: Serialize> {
     pub name: String,
 
-    // TODO: probably need to have behaviors return results
-    behaviors: HashMap<String, Box<dyn Fn() -> R + 'a>>,
+    behaviors: HashMap<String, Box<dyn Fn() -> Result<R, BehaviorError> + 'a>>,
 
     // Sometimes you don't want an experiment to run. Say, disabling a new codepath for anyone
     // who isn't staff. You can disable an experiment by setting a run_if block.
@@ -114,49 +69,38 @@ pub struct Experiment<'a, R: Clone + PartialEq + Serialize> {
     /// control value. Otherwise it defers to the experiment's configured enabled method.
     pub run_if_block: Option<Box<dyn Fn() -> bool + 'a>>,
     pub before_run_block: Option<Box<dyn Fn() + 'a>>,
-    pub cleaner: Option<CleanerBlock<R>>,
-    enabled: EnabledFn, // TODO: should this be a fn pointer or Fn?
+    pub cleaner: Option<CleanerBlock<'a, R>>,
+    enabled: EnabledBlock<'a>,
+    sampler: Option<Box<dyn Sampler + 'a>>,
     pub context: Context, /* TODO: maybe AHashMap<String, Box<dyn Any>>, https://github.com/actix/actix-web/blob/7dc034f0fb70846d9bb3445a2414a142356892e1/actix-http/src/extensions.rs */
-    // ignores: Vec<IgnoresBlock<R>>, //Vec<fn(&Observation<R>, &Observation<R>) -> bool>, // TODO: might need to return Result<bool>
     ignores: Vec<Box<dyn Fn(&Observation<R>, &Observation<R>) -> bool + 'a>>,
     pub err_on_mismatches: bool,
-    comparator: Option<ValueComparator<R>>,
-    error_comparator: Option<ErrorComparator>,
+    mismatch_error_block: Option<MismatchErrorBlock<'a, R>>,
+    comparator: ValueComparator<'a, R>,
+    error_comparator: Option<ErrorComparator<'a>>,
     pub publisher: Box<dyn Publisher<R> + 'a>, // TODO: make this an Option
+    error_handler: Option<ErrorHandler<'a>>,
+    /// first error the error handler decided to raise from a block which can't return it itself
+    raised_error: RefCell<Option<VictorsErrors>>,
+    /// deadline applied to every candidate without a deadline of its own.
+    /// Only honored by experiments that can abandon a candidate, see [crate::ParallelExperiment] and
+    /// [crate::AsyncExperiment]
+    timeout: Option<Duration>,
+    candidate_timeouts: HashMap<String, Duration>,
 }
 
-// impl<F: FnOnce(&Observation<R>, &Observation<R>) -> bool, R: Clone + PartialEq> Experimentation<F, R> for Experiment<R> {
-//     fn add_ignore(&mut self, block: F) {
-//         self.ignores.push(block);
-//     }
-// }
-
-impl<'a, R: Clone + PartialEq + Serialize> Experiment<'a, R> {
+impl<'a, R: PartialEq + Serialize> Experiment<'a, R> {
     /// Creates a new experiment with the name "experiment"
     pub fn default() -> Self {
         return Experiment::new(DEFAULT_EXPERIMENT_NAME);
     }
 
-    /// Creates a new experiment
+    /// Creates a new experiment whose values are compared with `==`
     ///
     /// # Arguments
     /// * `name` - the name of the experiment
     pub fn new(name: &'static str) -> Self {
-        return Self {
-            name: name.to_string(),
-            behaviors: Default::default(),
-            run_if_block: None,
-            before_run_block: None,
-            cleaner: None,
-            enabled: || true,
-            context: Default::default(),
-            ignores: vec![],
-            err_on_mismatches: false,
-            comparator: None,
-            error_comparator: None,
-            // publisher: |result| {}
-            publisher: Box::new(NoopPublisher {}),
-        };
+        return Experiment::with_comparator(name, |a: &R, b: &R| a == b);
     }
 
     /// Creates a new experiment with initial context
@@ -165,20 +109,72 @@ impl<'a, R: Clone + PartialEq + Serialize> Experiment<'a, R> {
     /// * `name` - the name of the experiment
     /// * `context` - Map of extra experiment data
     pub fn new_with_context(name: &'static str, context: Context) -> Self {
+        let mut experiment = Experiment::new(name);
+        experiment.context = context;
+        return experiment;
+    }
+}
+
+impl<'a, R: Serialize> Experiment<'a, R> {
+    /// Creates a new experiment whose values are compared with the given comparator.
+    ///
+    /// Experiments over types that don't implement `PartialEq` have to be created this way.
+    ///
+    /// ```
+    /// use serde::Serialize;
+    /// use victors::Experiment;
+    ///
+    /// #[derive(Clone, Serialize)]
+    /// struct Reading {
+    ///     celsius: f64,
+    /// }
+    ///
+    /// let mut experiment = Experiment::with_comparator("readings", |a: &Reading, b: &Reading| {
+    ///     (a.celsius - b.celsius).abs() < 0.01
+    /// });
+    /// experiment.control(|| Reading { celsius: 20.0 }).unwrap();
+    /// ```
+    ///
+    /// ```compile_fail
+    /// # use serde::Serialize;
+    /// # use victors::Experiment;
+    /// #[derive(Clone, Serialize)]
+    /// struct Reading {
+    ///     celsius: f64,
+    /// }
+    ///
+    /// let mut experiment = Experiment::new("readings");
+    /// experiment.control(|| Reading { celsius: 20.0 }).unwrap();
+    /// ```
+    ///
+    /// # Arguments
+    /// * `name` - the name of the experiment
+    /// * `comparator` - The block must take two arguments, the control value and a candidate value,
+    ///                  and return true or false. See [Experiment::comparator]
+    pub fn with_comparator<F>(name: &'static str, comparator: F) -> Self
+    where
+        F: Fn(&R, &R) -> bool + 'a,
+    {
         return Self {
             name: name.to_string(),
             behaviors: Default::default(),
             run_if_block: None,
             before_run_block: None,
             cleaner: None,
-            enabled: || true,
-            context,
+            enabled: Box::new(|| true),
+            sampler: None,
+            context: Default::default(),
             ignores: vec![],
-            err_on_mismatches: false,
-            comparator: None,
+            err_on_mismatches: victor::err_on_mismatches(),
+            mismatch_error_block: None,
+            comparator: Box::new(move |a, b| Comparison::from(comparator(a, b))),
             error_comparator: None,
             // publisher: |result| {}
-            publisher: Box::new(NoopPublisher {}),
+            publisher: Box::new(victor::publisher()),
+            error_handler: None,
+            raised_error: RefCell::new(None),
+            timeout: None,
+            candidate_timeouts: Default::default(),
         };
     }
 
@@ -193,7 +189,7 @@ impl<'a, R: Clone + PartialEq + Serialize> Experiment<'a, R> {
     fn run_if_block_allows(&self) -> bool {
         match &self.run_if_block {
             None => true,
-            Some(block) => block(),
+            Some(block) => self.guard(Operation::RunIf, false, block),
         }
     }
 
@@ -202,7 +198,7 @@ impl<'a, R: Clone + PartialEq + Serialize> Experiment<'a, R> {
     where
         F: Fn() -> R + 'a,
     {
-        self.add_behavior(DEFAULT_CANDIDATE_NAME, f)
+        self.add_behavior(DEFAULT_CANDIDATE_NAME, move || Ok::<R, Infallible>(f()))
     }
 
     /// Register a named candidate behavior for this experiment.
@@ -210,20 +206,54 @@ impl<'a, R: Clone + PartialEq + Serialize> Experiment<'a, R> {
     where
         F: Fn() -> R + 'a,
     {
-        self.add_behavior(name, f)
+        self.add_behavior(name, move || Ok::<R, Infallible>(f()))
     }
 
     /// Register the control behavior for this experiment.
     pub fn control<F>(&mut self, f: F) -> VictorsResult<()>
     where
         F: Fn() -> R + 'a,
+    {
+        self.add_behavior(CONTROL_NAME, move || Ok::<R, Infallible>(f()))
+    }
+
+    /// Register a fallible candidate behavior for this experiment, defaults name to "candidate".
+    ///
+    /// Errors returned by candidates are recorded on their observation and are never returned
+    /// from [Experiment::run].
+    pub fn try_candidate<F, E>(&mut self, f: F) -> VictorsResult<()>
+    where
+        F: Fn() -> Result<R, E> + 'a,
+        E: Into<BehaviorError>,
+    {
+        self.add_behavior(DEFAULT_CANDIDATE_NAME, f)
+    }
+
+    /// Register a named fallible candidate behavior for this experiment.
+    pub fn try_candidate_with_name<F, E>(&mut self, name: &str, f: F) -> VictorsResult<()>
+    where
+        F: Fn() -> Result<R, E> + 'a,
+        E: Into<BehaviorError>,
+    {
+        self.add_behavior(name, f)
+    }
+
+    /// Register a fallible control behavior for this experiment.
+    ///
+    /// An error returned by the control is recorded on its observation, published, and then
+    /// returned from [Experiment::run] as [VictorsErrors::BehaviorFailed].
+    pub fn try_control<F, E>(&mut self, f: F) -> VictorsResult<()>
+    where
+        F: Fn() -> Result<R, E> + 'a,
+        E: Into<BehaviorError>,
     {
         self.add_behavior(CONTROL_NAME, f)
     }
 
-    fn add_behavior<F>(&mut self, name: &str, f: F) -> VictorsResult<()>
+    fn add_behavior<F, E>(&mut self, name: &str, f: F) -> VictorsResult<()>
     where
-        F: Fn() -> R + 'a,
+        F: Fn() -> Result<R, E> + 'a,
+        E: Into<BehaviorError>,
     {
         if self.behaviors.contains_key(name) {
             return Err(VictorsErrors::BehaviorNotUnique(BehaviorNotUnique {
@@ -231,7 +261,7 @@ impl<'a, R: Clone + PartialEq + Serialize> Experiment<'a, R> {
                 name: name.to_string(),
             }));
         }
-        self.behaviors.insert(name.to_string(), Box::new(f));
+        self.behaviors.insert(name.to_string(), Box::new(move || f().map_err(Into::into)));
 
         return Ok(());
     }
@@ -245,49 +275,296 @@ impl<'a, R: Clone + PartialEq + Serialize> Experiment<'a, R> {
     }
 
     /// A block to clean an observed value for publishing or storing.
-    /// The block takes one argument, the observed value which will be cleaned.
-    pub fn clean(&mut self, f: CleanerBlock<R>) {
-        self.cleaner = Some(f)
+    ///
+    /// The block takes one argument, a reference to the observed value, and returns the value to
+    /// publish in its place, which may be of a different type. Use it to strip sensitive data or
+    /// shrink large values. Observations are still compared using their raw values.
+    pub fn clean<F, C>(&mut self, f: F)
+    where
+        F: Fn(&R) -> C + 'a,
+        C: Serialize,
+    {
+        self.cleaner = Some(Box::new(move |value| Ok(serde_json::to_value(f(value))?)))
+    }
+
+    /// Clean a value with the configured clean block.
+    ///
+    /// Returns None, so that the raw value is published, if no clean block is configured or the
+    /// clean block fails. Failures are handed to the error handler as [Operation::Clean].
+    pub fn clean_value(&self, value: &R) -> Option<Value> {
+        return self.clean_with(self.cleaner.as_ref()?, value);
+    }
+
+    /// Clean a value with the given clean block, handing failures to the error handler as
+    /// [Operation::Clean]. Returns None if the clean block fails
+    pub(crate) fn clean_with<T>(&self, cleaner: &dyn Fn(&T) -> VictorsResult<Value>, value: &T) -> Option<Value> {
+        return match unwind::catch(|| cleaner(value)) {
+            Ok(Ok(cleaned)) => Some(cleaned),
+            Ok(Err(error)) => {
+                self.defer_raised(Operation::Clean, error);
+                None
+            }
+            Err(panic) => {
+                self.defer_raised(Operation::Clean, self.operation_failed(Operation::Clean, panic));
+                None
+            }
+        };
+    }
+
+    /// Run every behavior and gather the observations into an experiment result.
+    ///
+    /// The error of the behavior named `name`, if it failed, is returned alongside the result so
+    /// that it can be handed back to the caller once the result has been published.
+    fn generate_result(&self, name: String) -> VictorsResult<(ExperimentResult<R>, Option<BehaviorError>)> {
+        return self.run_behaviors_now(&self.behaviors, &name, |key, behavior| {
+            // candidates are isolated so that a panicking candidate never takes the control down with it
+            return Some(if key == name { Ok(behavior()) } else { unwind::catch(behavior) });
+        });
     }
 
-    fn generate_result(&self, name: String) -> VictorsResult<ExperimentResult<R>> {
+    /// Run every behavior once, in a random order, and gather the observations into an
+    /// experiment result.
+    ///
+    /// `invoke` runs a single behavior given its name, resolving to None when the behavior was
+    /// abandoned past its deadline. The error of the behavior named `name`, if it failed, is
+    /// returned alongside the result so that it can be handed back to the caller once the result
+    /// has been published.
+    pub(crate) async fn run_behaviors<B, F, Fut>(
+        &self,
+        behaviors: &HashMap<String, B>,
+        name: &str,
+        mut invoke: F,
+    ) -> VictorsResult<(ExperimentResult<R>, Option<BehaviorError>)>
+    where
+        F: FnMut(&str, &B) -> Fut,
+        Fut: Future<Output = Option<BehaviorOutcome<R>>>,
+    {
         let mut observations = vec![];
         let mut observation_to_return_index = None;
+        let mut error_to_return = None;
 
         // TODO: better way to get keys and shuffle?
-        let mut keys = Vec::from_iter(self.behaviors.keys().cloned());
+        let mut keys = Vec::from_iter(behaviors.keys().cloned());
         keys.shuffle(&mut thread_rng());
         for (i, key) in keys.iter().enumerate() {
-            let behavior = self.behaviors.get(key);
-            if let Some(behavior) = behavior {
+            if let Some(behavior) = behaviors.get(key) {
                 let start = Instant::now();
-                let behavior_results = behavior();
-                let duration = start.elapsed();
-                // TODO: need to clean value at some point
-                let observation = Observation::new(
-                    key.to_string(),
-                    self.name.to_string(),
-                    behavior_results,
-                    None,
-                    duration.as_millis(),
-                );
+                let behavior_results = match invoke(key, behavior).await {
+                    Some(behavior_results) => behavior_results,
+                    None => {
+                        observations.push(Observation::new_timed_out(
+                            key.to_string(),
+                            self.name.to_string(),
+                            start.elapsed().as_millis(),
+                        ));
+                        continue;
+                    }
+                };
+                let (observation, error) = self.observe(key, behavior_results, start.elapsed());
 
                 observations.push(observation);
-                if key == &name {
+                if key == name {
                     observation_to_return_index = Some(i);
+                    error_to_return = error;
                 }
             }
         }
 
-        match observation_to_return_index {
-            None => Err(VictorsErrors::BehaviorMissing(BehaviorMissing {
-                experiment_name: self.name.to_string(),
-                name,
-            })),
+        let result = self.build_result(name, observations, observation_to_return_index)?;
+        return Ok((result, error_to_return));
+    }
+
+    /// [Experiment::run_behaviors] for behaviors which run to completion when invoked
+    pub(crate) fn run_behaviors_now<B, F>(
+        &self,
+        behaviors: &HashMap<String, B>,
+        name: &str,
+        mut invoke: F,
+    ) -> VictorsResult<(ExperimentResult<R>, Option<BehaviorError>)>
+    where
+        F: FnMut(&str, &B) -> Option<BehaviorOutcome<R>>,
+    {
+        let run = self.run_behaviors(behaviors, name, |key, behavior| future::ready(invoke(key, behavior)));
+        return poll_ready(run);
+    }
+
+    /// Record the outcome of running a behavior as an observation.
+    ///
+    /// Returns the error the behavior returned, if any, alongside the observation.
+    pub(crate) fn observe(
+        &self,
+        name: &str,
+        outcome: BehaviorOutcome<R>,
+        duration: Duration,
+    ) -> (Observation<R>, Option<BehaviorError>) {
+        return match outcome {
+            Ok(Ok(value)) => {
+                let cleaned_value = self.clean_value(&value);
+                (
+                    Observation::new(name.to_string(), self.name.to_string(), value, cleaned_value, duration.as_millis()),
+                    None,
+                )
+            }
+            Ok(Err(err)) => (
+                Observation::new_exception(
+                    name.to_string(),
+                    self.name.to_string(),
+                    err.to_string(),
+                    duration.as_millis(),
+                ),
+                Some(err),
+            ),
+            Err(panic) => (
+                Observation::new_panic(
+                    name.to_string(),
+                    self.name.to_string(),
+                    panic.message,
+                    panic.backtrace,
+                    duration.as_millis(),
+                ),
+                None,
+            ),
+        };
+    }
+
+    /// Build the experiment result from the observations, where the observation at `index` is the
+    /// one whose value is returned.
+    pub(crate) fn build_result(
+        &self,
+        name: &str,
+        observations: Vec<Observation<R>>,
+        index: Option<usize>,
+    ) -> VictorsResult<ExperimentResult<R>> {
+        return match index {
+            None => Err(self.behavior_missing(name)),
             Some(o) => Ok(ExperimentResult::new(&self, observations, o)),
+        };
+    }
+
+    /// Publish the result and return the value of its control observation.
+    ///
+    /// # Arguments
+    /// * `name` - name of the behavior whose value is returned
+    /// * `result` - the experiment result to publish
+    /// * `error` - error returned by the named behavior, if any
+    pub(crate) fn publish_result(
+        &self,
+        name: &str,
+        result: ExperimentResult<R>,
+        error: Option<BehaviorError>,
+    ) -> VictorsResult<R> {
+        // a block building the result failed and the error handler decided to raise it
+        self.take_raised()?;
+
+        if let Err(source) = self.publisher.publish(&result) {
+            self.raised(
+                Operation::Publish,
+                VictorsErrors::PublishFailed(PublishFailed {
+                    experiment_name: self.name.to_string(),
+                    source,
+                }),
+            )?;
+        }
+
+        if self.err_on_mismatches && result.has_mismatches() {
+            return Err(match &self.mismatch_error_block {
+                None => VictorsErrors::MismatchError(Box::new(MismatchError::new(&result))),
+                Some(block) => VictorsErrors::CustomMismatch(CustomMismatch {
+                    experiment_name: self.name.to_string(),
+                    source: block(&result),
+                }),
+            });
+        }
+
+        if let Some(err) = error {
+            return Err(self.behavior_failed(name, err));
+        }
+
+        // publishers only borrowed the result so the value can be moved back to the caller
+        return match result.into_control().and_then(|control| control.value) {
+            Some(value) => Ok(value),
+            None => Err(VictorsErrors::NoValue(name.to_string())),
+        };
+    }
+
+    /// Hand an error raised by `operation` to the error handler.
+    ///
+    /// # Return
+    /// Returns the error when the handler decides it should be raised
+    pub(crate) fn raised(&self, operation: Operation, error: VictorsErrors) -> VictorsResult<()> {
+        let action = match &self.error_handler {
+            Some(handler) => handler(operation, &error),
+            None => ErrorAction::Log,
+        };
+        return match action {
+            ErrorAction::Log => {
+                log::error!("experiment {} {} failed: {}", self.name, operation, error);
+                Ok(())
+            }
+            ErrorAction::Swallow => Ok(()),
+            ErrorAction::Raise => Err(error),
+        };
+    }
+
+    /// Hand an error to the error handler from a block that can't return it, keeping it to be
+    /// returned from `run` when the handler decides to raise it.
+    fn defer_raised(&self, operation: Operation, error: VictorsErrors) {
+        if let Err(error) = self.raised(operation, error) {
+            let mut raised_error = self.raised_error.borrow_mut();
+            if raised_error.is_none() {
+                *raised_error = Some(error);
+            }
         }
     }
 
+    /// Returns the error kept by [Experiment::defer_raised], if any
+    fn take_raised(&self) -> VictorsResult<()> {
+        return match self.raised_error.take() {
+            None => Ok(()),
+            Some(error) => Err(error),
+        };
+    }
+
+    /// Run a user supplied block, handing any panic to the error handler.
+    ///
+    /// # Arguments
+    /// * `operation` - the operation the block is part of
+    /// * `fallback` - returned in place of the block's value when it panics
+    /// * `block` - the block to run
+    fn guard<T, F: FnOnce() -> T>(&self, operation: Operation, fallback: T, block: F) -> T {
+        return match unwind::catch(block) {
+            Ok(value) => value,
+            Err(panic) => {
+                self.defer_raised(operation, self.operation_failed(operation, panic));
+                fallback
+            }
+        };
+    }
+
+    fn operation_failed(&self, operation: Operation, panic: CaughtPanic) -> VictorsErrors {
+        return VictorsErrors::OperationFailed(OperationFailed {
+            experiment_name: self.name.to_string(),
+            operation,
+            message: panic.message,
+            backtrace: panic.backtrace,
+        });
+    }
+
+    pub(crate) fn behavior_failed(&self, name: &str, source: BehaviorError) -> VictorsErrors {
+        return VictorsErrors::BehaviorFailed(BehaviorFailed {
+            experiment_name: self.name.to_string(),
+            name: name.to_string(),
+            source,
+        });
+    }
+
+    pub(crate) fn behavior_missing(&self, name: &str) -> VictorsErrors {
+        return VictorsErrors::BehaviorMissing(BehaviorMissing {
+            experiment_name: self.name.to_string(),
+            name: name.to_string(),
+        });
+    }
+
     pub fn add_context(&mut self, context: Context) {
         self.context.extend(context);
     }
@@ -308,7 +585,8 @@ impl<'a, R: Clone + PartialEq + Serialize> Experiment<'a, R> {
     /// Ignore a mismatched observation
     ///
     /// Iterates through the configured ignore blocks and calls each of them with the given
-    /// control and mismatched candidate observations.
+    /// control and mismatched candidate observations until one of them returns true.
+    /// A block that panics is handed to the error handler and treated as returning false.
     ///
     /// # Arguments
     /// * `control` - the control observation
@@ -321,20 +599,70 @@ impl<'a, R: Clone + PartialEq + Serialize> Experiment<'a, R> {
             return false;
         }
 
-        return self.ignores.iter().any(|ignore| ignore(&control, &candidate));
+        return self
+            .ignores
+            .iter()
+            .any(|ignore| self.guard(Operation::Ignore, false, || ignore(&control, &candidate)));
     }
 
-    // TODO: does this need to return a result?
+    /// Whether the observations are equivalent, using the configured comparators.
+    /// A comparator that panics is handed to the error handler and the observations are treated
+    /// as mismatched.
     pub fn observations_are_equivalent(&self, a: &Observation<R>, b: &Observation<R>) -> bool {
-        return a.equivalent_to(b, self.comparator, self.error_comparator);
+        return self.compare_observations(a, b).is_equivalent();
+    }
+
+    /// Compare the observations using the configured comparators, with the reasons they differ
+    /// when the comparator explains them. See [Experiment::observations_are_equivalent]
+    pub fn compare_observations(&self, a: &Observation<R>, b: &Observation<R>) -> Comparison {
+        let operation = if a.raised() && b.raised() {
+            Operation::ErrorCompare
+        } else {
+            Operation::Compare
+        };
+        return self.guard(operation, Comparison::different(format!("{} block panicked", operation)), || {
+            a.compare(b, &self.comparator, self.error_comparator.as_deref())
+        });
     }
 
-    pub fn enabled(&mut self, enabled: fn() -> bool) {
-        self.enabled = enabled;
+    /// Define a block that determines whether or not the experiment is enabled.
+    pub fn enabled<F>(&mut self, enabled: F)
+    where
+        F: Fn() -> bool + 'a,
+    {
+        self.enabled = Box::new(enabled);
     }
 
+    /// Whether the experiment is enabled. Disabled if the enabled block panics
     pub fn is_enabled(&self) -> bool {
-        return (self.enabled)();
+        return self.guard(Operation::Enabled, false, &self.enabled);
+    }
+
+    /// Only run the candidates for the runs picked by the given sampler.
+    /// See [Experiment::percent_enabled]
+    pub fn sampler<S: Sampler + 'a>(&mut self, sampler: S) {
+        self.sampler = Some(Box::new(sampler));
+    }
+
+    /// Only run the candidates for a random `percent` percent of runs, the rest merely return the
+    /// control value. The rate is recorded on every published result.
+    ///
+    /// # Arguments
+    /// * `percent` - between 0 and 100, NaN is treated as 0
+    pub fn percent_enabled(&mut self, percent: f64) {
+        self.sampler(PercentSampler::new(percent));
+    }
+
+    /// Fraction of runs, between 0 and 1, that run their candidates
+    pub fn sampling_rate(&self) -> f64 {
+        return self.sampler.as_ref().map_or(1.0, |sampler| sampler.rate());
+    }
+
+    fn is_sampled(&self) -> bool {
+        return match &self.sampler {
+            None => true,
+            Some(sampler) => self.guard(Operation::Sample, false, || sampler.sample(&self.name, &self.context)),
+        };
     }
 
     // // Don't publish anything.
@@ -343,6 +671,27 @@ impl<'a, R: Clone + PartialEq + Serialize> Experiment<'a, R> {
         self.publisher = Box::new(publisher);
     }
 
+    /// Decide what happens when an operation of the experiment, such as publishing its result,
+    /// fails. Errors are logged by default, so they never affect the value returned by `run`.
+    ///
+    /// User supplied blocks that panic are reported as [VictorsErrors::OperationFailed] and, unless
+    /// the error is raised, the experiment carries on as follows
+    /// * `compare` and `error_compare` - the observations mismatch
+    /// * `ignore` - the block doesn't ignore the mismatch, the remaining ignore blocks are tried
+    /// * `run_if`, `enabled` and `sample` - the candidates don't run
+    /// * `before_run` - the candidates don't run
+    /// * `clean` - the raw value is published
+    ///
+    /// # Arguments
+    /// * `handler` - given the failed operation and its error, returns whether the error is
+    ///   logged, swallowed or returned from `run`
+    pub fn on_error<F>(&mut self, handler: F)
+    where
+        F: Fn(Operation, &VictorsErrors) -> ErrorAction + 'a,
+    {
+        self.error_handler = Some(Box::new(handler));
+    }
+
     // TODO: run needs to return a generic result
 
     // TODO: not sure I love passing in name to mean return result from behavior with name
@@ -353,8 +702,6 @@ impl<'a, R: Clone + PartialEq + Serialize> Experiment<'a, R> {
     // would a enum help here?
     // issue is that they share similar behavior
 
-    // Need a fn to run control/candidates in parallel
-
     /// Run all the behaviors for this experiment, observing each and publishing the results.
     /// Return the result of the control
     /// See [internal_run]
@@ -362,6 +709,17 @@ impl<'a, R: Clone + PartialEq + Serialize> Experiment<'a, R> {
         return self.internal_run(CONTROL_NAME);
     }
 
+    /// Same as [Experiment::run] but returns the caller's own error type.
+    ///
+    /// Errors built by the [Experiment::raise_with] block, and errors returned by the control, are
+    /// returned as they are when they are of type `E`. See [VictorsErrors::into_error]
+    pub fn run_with_error<E>(&mut self) -> Result<R, E>
+    where
+        E: From<VictorsErrors> + StdError + 'static,
+    {
+        return self.run().map_err(VictorsErrors::into_error);
+    }
+
     /// Run all the behaviors for this experiment, observing each and publishing the results.
     /// Return the result of the named behavior
     ///
@@ -371,56 +729,68 @@ impl<'a, R: Clone + PartialEq + Serialize> Experiment<'a, R> {
         let block = self.behaviors.get(name);
         match block {
             None => {
-                return Err(VictorsErrors::BehaviorMissing(BehaviorMissing {
-                    experiment_name: (*&self.name).to_string(),
-                    name: name.to_string(),
-                }));
+                return Err(self.behavior_missing(name));
             }
             Some(block) => {
-                if !self.should_experiment_run() {
-                    return Ok(block());
+                if !self.start_run(self.behaviors.len())? {
+                    return block().map_err(|err| self.behavior_failed(name, err));
                 }
             }
         }
 
-        if let Some(before_block) = &self.before_run_block {
-            before_block()
-        }
+        let (result, error) = self.generate_result(name.to_string())?;
+        return self.publish_result(name, result, error);
+    }
 
-        let result = self.generate_result(name.to_string())?;
-        // TODO: this should return a VictorsError<()> to handle errors?
-        // ruby version has a `raised` fn that takes in operation and error and allows users to
-        // customize behavior. Default behavior is to re-raise the exception
-        self.publisher.publish(&result);
+    /// Decide whether this run runs its candidates, running the before_run block if it does.
+    ///
+    /// # Arguments
+    /// * `behavior_count` - number of behaviors registered, including the control
+    ///
+    /// # Return
+    /// Returns whether to run the candidates, or the error of a failing block when the error
+    /// handler decides to raise it
+    pub(crate) fn start_run(&self, behavior_count: usize) -> VictorsResult<bool> {
+        self.raised_error.replace(None);
+        let run_candidates = behavior_count > 1 && self.should_run_candidates() && self.run_before_block();
+        self.take_raised()?;
+        return Ok(run_candidates);
+    }
 
-        if self.err_on_mismatches && result.has_mismatches() {
-            // TODO: do we want to support custom mismatch error?
-            // ruby version has a `raise_with` fn that sets `@_scientist_custom_mismatch_error`
-            // to allow for a custom err to be raised
-            return Err(VictorsErrors::MismatchError(MismatchError {
-                experiment_name: self.name.to_string(),
-                exception: None,
-                message: "".to_string(),
-                backtrace: None,
-            }));
-        }
+    /// Whether the experiment is enabled and allowed to run its candidates, regardless of how many
+    /// behaviors are registered.
+    fn should_run_candidates(&self) -> bool {
+        return self.is_enabled() && self.is_sampled() && self.run_if_block_allows();
+    }
+
+    pub(crate) fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = Some(timeout);
+    }
 
-        // if let Some(err) = &result.control.exception {
-        //     todo!()
-        // } else {
-        //     return Ok(result.control.value.to_owned());
-        // }
+    pub(crate) fn set_candidate_timeout(&mut self, name: &str, timeout: Duration) {
+        self.candidate_timeouts.insert(name.to_string(), timeout);
+    }
 
-        // TODO: fix unwrap
-        return Ok(result.control().unwrap().value.to_owned());
+    /// Returns how long the named candidate may run before it is abandoned, if at all
+    pub(crate) fn candidate_timeout(&self, name: &str) -> Option<Duration> {
+        return self.candidate_timeouts.get(name).copied().or(self.timeout);
     }
 
-    fn should_experiment_run(&self) -> bool {
-        return self.behaviors.len() > 1 && self.is_enabled() && self.run_if_block_allows();
+    /// Run the before_run block, if any. Returns false if it panicked
+    fn run_before_block(&self) -> bool {
+        return match &self.before_run_block {
+            None => true,
+            Some(before_block) => self.guard(Operation::BeforeRun, false, || {
+                before_block();
+                true
+            }),
+        };
     }
 
     /// Whether to return an error when the control and candidate mismatch.
-    fn err_on_mismatches(&mut self, err_on_mismatches: bool) {
+    ///
+    /// Defaults to the process wide setting, see [victor::set_err_on_mismatches].
+    pub fn err_on_mismatches(&mut self, err_on_mismatches: bool) {
         self.err_on_mismatches = err_on_mismatches;
     }
 
@@ -429,8 +799,43 @@ impl<'a, R: Clone + PartialEq + Serialize> Experiment<'a, R> {
     /// # Arguments
     /// * `comparator` - The block must take two arguments, the control value and a candidate value,
     ///                  and return true or false.
-    pub fn comparator(&mut self, comparator: fn(a: &R, b: &R) -> bool) {
-        self.comparator = Some(comparator);
+    pub fn comparator<F>(&mut self, comparator: F)
+    where
+        F: Fn(&R, &R) -> bool + 'a,
+    {
+        self.comparator = Box::new(move |a, b| Comparison::from(comparator(a, b)));
+    }
+
+    /// A block which compares two experimental values and explains why they differ.
+    /// Replaces the [Experiment::comparator].
+    ///
+    /// The reasons are recorded on each mismatched observation as `mismatch_reasons`, so they are
+    /// published with the result and shown by [MismatchError].
+    ///
+    /// # Arguments
+    /// * `comparator` - The block must take two arguments, the control value and a candidate value,
+    ///                  and return whether they are equivalent or the reasons they differ.
+    pub fn explaining_comparator<F>(&mut self, comparator: F)
+    where
+        F: Fn(&R, &R) -> Comparison + 'a,
+    {
+        self.comparator = Box::new(comparator);
+    }
+
+    /// A block which builds the error returned when the observations mismatch, in place of
+    /// [MismatchError]. Only used when the experiment errs on mismatches.
+    ///
+    /// The error is returned from `run` as [VictorsErrors::CustomMismatch], or as it is from
+    /// [Experiment::run_with_error].
+    ///
+    /// # Arguments
+    /// * `block` - given the mismatched experiment result, returns the error
+    pub fn raise_with<F, E>(&mut self, block: F)
+    where
+        F: Fn(&ExperimentResult<R>) -> E + 'a,
+        E: StdError + Send + Sync + 'static,
+    {
+        self.mismatch_error_block = Some(Box::new(move |result| Box::new(block(result))));
     }
 
     /// A block which compares two experimental errors.
@@ -438,16 +843,19 @@ impl<'a, R: Clone + PartialEq + Serialize> Experiment<'a, R> {
     /// # Arguments
     /// * `comparator` - The block must take two arguments, the control error and a candidate error,
     ///                  and return true or false.
-    pub fn error_comparator(&mut self, comparator: ErrorComparator) {
-        self.error_comparator = Some(comparator);
+    pub fn error_comparator<F>(&mut self, comparator: F)
+    where
+        F: Fn(&String, &String) -> bool + 'a,
+    {
+        self.error_comparator = Some(Box::new(comparator));
     }
 }
 
-pub struct UncontrolledExperiment<'a, R: Clone + PartialEq + Serialize> {
+pub struct UncontrolledExperiment<'a, R: Serialize> {
     experiment: Experiment<'a, R>,
 }
 
-impl<'a, R: Clone + PartialEq + Serialize> UncontrolledExperiment<'a, R> {
+impl<'a, R: PartialEq + Serialize> UncontrolledExperiment<'a, R> {
     /// Creates a new experiment with the name "experiment"
     pub fn default() -> Self {
         return Self {
@@ -455,7 +863,7 @@ impl<'a, R: Clone + PartialEq + Serialize> UncontrolledExperiment<'a, R> {
         };
     }
 
-    /// Creates a new experiment
+    /// Creates a new experiment whose values are compared with `==`
     ///
     /// # Arguments
     /// * `name` - the name of the experiment
@@ -475,9 +883,18 @@ impl<'a, R: Clone + PartialEq + Serialize> UncontrolledExperiment<'a, R> {
             experiment: Experiment::new_with_context(name, context),
         };
     }
+}
 
-    fn run_if_block_allows(&self) -> bool {
-        self.experiment.run_if_block_allows()
+impl<'a, R: Serialize> UncontrolledExperiment<'a, R> {
+    /// Creates a new experiment whose values are compared with the given comparator.
+    /// See [Experiment::with_comparator]
+    pub fn with_comparator<F>(name: &'static str, comparator: F) -> Self
+    where
+        F: Fn(&R, &R) -> bool + 'a,
+    {
+        return Self {
+            experiment: Experiment::with_comparator(name, comparator),
+        };
     }
 
     /// Register a named candidate behavior for this experiment
@@ -488,6 +905,16 @@ impl<'a, R: Clone + PartialEq + Serialize> UncontrolledExperiment<'a, R> {
         self.experiment.candidate_with_name(name, f)
     }
 
+    /// Register a named fallible candidate behavior for this experiment.
+    /// See [Experiment::try_candidate_with_name]
+    pub fn try_candidate<F, E>(&mut self, name: &str, f: F) -> VictorsResult<()>
+    where
+        F: Fn() -> Result<R, E> + 'a,
+        E: Into<BehaviorError>,
+    {
+        self.experiment.try_candidate_with_name(name, f)
+    }
+
     /// Define a block of code to run before an experiment begins, if the experiment is enabled.
     pub fn before_run<F>(&mut self, f: F)
     where
@@ -497,8 +924,12 @@ impl<'a, R: Clone + PartialEq + Serialize> UncontrolledExperiment<'a, R> {
     }
 
     /// A block to clean an observed value for publishing or storing.
-    /// The block takes one argument, the observed value which will be cleaned.
-    pub fn clean(&mut self, f: CleanerBlock<R>) {
+    /// See [Experiment::clean]
+    pub fn clean<F, C>(&mut self, f: F)
+    where
+        F: Fn(&R) -> C + 'a,
+        C: Serialize,
+    {
         self.experiment.clean(f)
     }
 
@@ -524,12 +955,27 @@ impl<'a, R: Clone + PartialEq + Serialize> UncontrolledExperiment<'a, R> {
         self.experiment.ignore_mismatch_observation(control, candidate)
     }
 
-    fn enabled(&mut self, enabled: fn() -> bool) {
+    /// See [Experiment::enabled]
+    pub fn enabled<F>(&mut self, enabled: F)
+    where
+        F: Fn() -> bool + 'a,
+    {
         self.experiment.enabled(enabled)
     }
 
-    fn is_enabled(&self) -> bool {
-        return (self.experiment.enabled)();
+    /// See [Experiment::sampler]
+    pub fn sampler<S: Sampler + 'a>(&mut self, sampler: S) {
+        self.experiment.sampler(sampler)
+    }
+
+    /// See [Experiment::percent_enabled]
+    pub fn percent_enabled(&mut self, percent: f64) {
+        self.experiment.percent_enabled(percent)
+    }
+
+    /// See [Experiment::is_enabled]
+    pub fn is_enabled(&self) -> bool {
+        return self.experiment.is_enabled();
     }
 
     // fn publish(&self, result: &ExperimentResult<R>) {
@@ -540,6 +986,14 @@ impl<'a, R: Clone + PartialEq + Serialize> UncontrolledExperiment<'a, R> {
         self.experiment.publisher = Box::new(publisher);
     }
 
+    /// Decide what happens when an operation of the experiment fails. See [Experiment::on_error]
+    pub fn on_error<F>(&mut self, handler: F)
+    where
+        F: Fn(Operation, &VictorsErrors) -> ErrorAction + 'a,
+    {
+        self.experiment.on_error(handler)
+    }
+
     /// Run all the behaviors for this experiment, observing each and publishing the results.
     /// Return the result of the named candidate
     /// See [Experiment::internal_run]
@@ -547,8 +1001,23 @@ impl<'a, R: Clone + PartialEq + Serialize> UncontrolledExperiment<'a, R> {
         return self.experiment.internal_run(name);
     }
 
-    fn should_experiment_run(&self) -> bool {
-        return self.experiment.should_experiment_run();
+    /// Same as [UncontrolledExperiment::run] but returns the caller's own error type.
+    /// See [Experiment::run_with_error]
+    pub fn run_with_error<E>(&mut self, name: &'static str) -> Result<R, E>
+    where
+        E: From<VictorsErrors> + StdError + 'static,
+    {
+        return self.run(name).map_err(VictorsErrors::into_error);
+    }
+
+    /// A block which builds the error returned when the observations mismatch.
+    /// See [Experiment::raise_with]
+    pub fn raise_with<F, E>(&mut self, block: F)
+    where
+        F: Fn(&ExperimentResult<R>) -> E + 'a,
+        E: StdError + Send + Sync + 'static,
+    {
+        self.experiment.raise_with(block)
     }
 
     pub fn observations_are_equivalent(&self, a: &Observation<R>, b: &Observation<R>) -> bool {
@@ -556,7 +1025,27 @@ impl<'a, R: Clone + PartialEq + Serialize> UncontrolledExperiment<'a, R> {
     }
 
     /// Whether to return an error when the control and candidate mismatch.
-    fn err_on_mismatches(&mut self, err_on_mismatches: bool) {
+    /// See [Experiment::err_on_mismatches]
+    pub fn err_on_mismatches(&mut self, err_on_mismatches: bool) {
         self.experiment.err_on_mismatches(err_on_mismatches);
     }
 }
+
+/// Drive a future which never waits on anything to completion, on the current thread.
+fn poll_ready<F: Future>(future: F) -> F::Output {
+    fn noop_raw_waker() -> RawWaker {
+        fn clone(_: *const ()) -> RawWaker {
+            return noop_raw_waker();
+        }
+        fn noop(_: *const ()) {}
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+        return RawWaker::new(ptr::null(), &VTABLE);
+    }
+
+    let waker = unsafe { Waker::from_raw(noop_raw_waker()) };
+    let mut future = Box::pin(future);
+    return match future.as_mut().poll(&mut task::Context::from_waker(&waker)) {
+        Poll::Ready(output) => output,
+        Poll::Pending => panic!("future was expected to be ready"),
+    };
+}
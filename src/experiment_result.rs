@@ -1,12 +1,21 @@
 use std::any::Any;
-use crate::{context::Context, experiment::Experiment, observation::Observation};
-use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use crate::{
+    context::Context,
+    diff::{self, Difference},
+    experiment::Experiment,
+    observation::Observation,
+    result_publisher::ScientistValue,
+};
+use once_cell::sync::OnceCell;
+use serde::{ser::SerializeStruct, Deserialize, Serialize, Serializer};
+use serde_json::Value;
 
 trait ExperimentValue: Clone {}
 
 /// The immutable result of running an experiment.
-#[derive(Clone, Debug, PartialEq, Serialize)]
-pub struct ExperimentResult<R: Clone + PartialEq + Serialize> {
+#[derive(Clone, Debug)]
+pub struct ExperimentResult<R: Serialize> {
     experiment_name: String,
     observations: Vec<Observation<R>>,
     // observations: Vec<Observation<dyn ExperimentValue + PartialEq>>,
@@ -16,9 +25,16 @@ pub struct ExperimentResult<R: Clone + PartialEq + Serialize> {
     control_index: usize,
     mismatched_indexes: Vec<usize>,
     ignored_indexes: Vec<usize>,
+    /// fraction of runs, between 0 and 1, that ran candidates when the result was produced
+    sampling_rate: f64,
+    /// structural differences between the control and each mismatched observation, by name.
+    /// Only computed once asked for, or when the result is serialized
+    diffs: OnceCell<BTreeMap<String, Vec<Difference>>>,
+    /// cleaned input the behaviors ran with, see [crate::InputExperiment::clean_input]
+    input: Option<Value>,
 }
 
-impl<'a, R: Clone + PartialEq + Serialize> ExperimentResult<R> {
+impl<'a, R: Serialize> ExperimentResult<R> {
 // impl<'a> ExperimentResult {
 
     /// Create a new experiment result
@@ -30,21 +46,32 @@ impl<'a, R: Clone + PartialEq + Serialize> ExperimentResult<R> {
     pub fn new(
     // pub fn new<R: ExperimentValue + PartialEq>(
         experiment: &'a Experiment<'_, R>,
-        observations: Vec<Observation<R>>,
+        mut observations: Vec<Observation<R>>,
         control_index: usize
     ) -> Self {
         let (mismatched_indexes, ignored_indexes) =
-            ExperimentResult::evaluate_candidates(experiment, &observations, control_index);
+            ExperimentResult::evaluate_candidates(experiment, &mut observations, control_index);
         Self {
             experiment_name: experiment.name.to_string(),
             observations,
+            // the experiment keeps its context for later runs, and results may be kept past this one,
+            // e.g. by a BufferedPublisher, so each result owns a copy
             context: experiment.context.clone(),
             control_index,
             mismatched_indexes,
             ignored_indexes,
+            sampling_rate: experiment.sampling_rate(),
+            diffs: OnceCell::new(),
+            input: None,
         }
     }
 
+    /// Returns this result with the cleaned input the behaviors ran with
+    pub(crate) fn with_input(mut self, input: Option<Value>) -> Self {
+        self.input = input;
+        return self;
+    }
+
     /// Returns experiment name corresponding to the results
     pub fn experiment_name(&self) -> &String {
         return &self.experiment_name;
@@ -56,11 +83,57 @@ impl<'a, R: Clone + PartialEq + Serialize> ExperimentResult<R> {
         return self.observations.get(self.control_index);
     }
 
+    /// Consumes the result, returning the control observation without copying its value
+    pub fn into_control(mut self) -> Option<Observation<R>> {
+        if self.control_index >= self.observations.len() {
+            return None;
+        }
+        return Some(self.observations.swap_remove(self.control_index));
+    }
+
+    /// Returns the cleaned input the behaviors ran with, if the experiment takes an input and
+    /// records it. See [crate::InputExperiment::clean_input]
+    pub fn input(&self) -> Option<&Value> {
+        return self.input.as_ref();
+    }
+
+    /// Returns the observations of every behavior that ran
+    pub fn observations(&self) -> &Vec<Observation<R>> {
+        return &self.observations;
+    }
+
     /// Returns reference to the experiment context
     pub fn context(&self) -> &Context {
         return &self.context;
     }
 
+    /// Returns the fraction of runs, between 0 and 1, that ran their candidates when this result
+    /// was produced. Divide counts of published results by it to extrapolate totals.
+    pub fn sampling_rate(&self) -> f64 {
+        return self.sampling_rate;
+    }
+
+    /// Returns the structural differences between the control's value and the value of the
+    /// mismatched observation with the given name, see [diff::diff].
+    ///
+    /// Cleaned values are compared when the experiment has a clean block. None if the observation
+    /// did not mismatch, or either observation has no value to compare, e.g. because its behavior
+    /// returned an error.
+    ///
+    /// # Arguments
+    /// * `name` - name of the mismatched observation
+    pub fn diff(&self, name: &str) -> Option<&Vec<Difference>> {
+        return self.diffs().get(name);
+    }
+
+    /// Returns the structural differences of every mismatched observation that has one, by name.
+    /// See [ExperimentResult::diff]
+    pub fn diffs(&self) -> &BTreeMap<String, Vec<Difference>> {
+        return self.diffs.get_or_init(|| {
+            ExperimentResult::diff_mismatches(&self.observations, self.control_index, &self.mismatched_indexes)
+        });
+    }
+
     /// Return mismatched observations
     pub fn mismatched(&self) -> Vec<&Observation<R>> {
         let mut mismatched = vec![];
@@ -97,39 +170,137 @@ impl<'a, R: Clone + PartialEq + Serialize> ExperimentResult<R> {
         return !self.ignored_indexes.is_empty();
     }
 
+    /// Returns a copy of this result with its observed values converted into [ScientistValue]s,
+    /// which publishers can handle without knowing the experiment's return type.
+    ///
+    /// Values that can not be serialized are left out.
+    pub fn erase(&self) -> ExperimentResult<ScientistValue> {
+        return self.map_values(|value| ScientistValue::from_serialize(value).ok());
+    }
+
+    /// Drops the raw values of observations that have a cleaned value, so they can't leak through
+    /// the result, e.g. when it is debug printed.
+    pub(crate) fn without_cleaned_raw_values(mut self) -> Self {
+        for observation in &mut self.observations {
+            if observation.cleaned_value.is_some() {
+                observation.value = None;
+            }
+        }
+        return self;
+    }
+
+    /// Returns a copy of this result with every observed value converted by `f`.
+    /// Values `f` returns None for are left out.
+    pub fn map_values<T, F>(&self, f: F) -> ExperimentResult<T>
+    where
+        T: Serialize,
+        F: Fn(&R) -> Option<T>,
+    {
+        return ExperimentResult {
+            experiment_name: self.experiment_name.clone(),
+            observations: self.observations.iter().map(|observation| observation.map_value(&f)).collect(),
+            context: self.context.clone(),
+            control_index: self.control_index,
+            mismatched_indexes: self.mismatched_indexes.clone(),
+            ignored_indexes: self.ignored_indexes.clone(),
+            sampling_rate: self.sampling_rate,
+            // the mapped values may not diff the same, or be left out, so keep the original diffs
+            diffs: OnceCell::from(self.diffs().clone()),
+            input: self.input.clone(),
+        };
+    }
+
+    /// Diff the value of each mismatched observation against the control's value.
+    fn diff_mismatches(
+        observations: &[Observation<R>],
+        control_index: usize,
+        mismatched_indexes: &[usize],
+    ) -> BTreeMap<String, Vec<Difference>> {
+        let mut diffs = BTreeMap::new();
+        let control_value = match observations.get(control_index).and_then(|control| control.json_value()) {
+            Some(value) => value,
+            None => return diffs,
+        };
+        for i in mismatched_indexes {
+            if let Some(observation) = observations.get(*i) {
+                if let Some(value) = observation.json_value() {
+                    diffs.insert(observation.name.clone(), diff::diff(&control_value, &value));
+                }
+            }
+        }
+        return diffs;
+    }
+
     // TODO: can evaluate candidate outside and then dont have to worry about lifetime
     /// Evaluate the candidates to find mismatched and ignored results.
+    /// Records why each of them differs from the control on its observation.
     fn evaluate_candidates(
         experiment: &'a Experiment<'_, R>,
-        observations: &'a Vec<Observation<R>>,
+        observations: &mut [Observation<R>],
         control_index: usize,
     ) -> (Vec<usize>, Vec<usize>) {
         let mut mismatched = vec![];
         let mut ignored = vec![];
+        let mut reasons = vec![];
         // TODO: what to do here. we enforce this so it shouldnt happen.
         let control = observations.get(control_index).unwrap();
         for (i, observation) in observations.iter().enumerate() {
-            let is_equivalent = experiment.observations_are_equivalent(&control, observation);
-            if !is_equivalent {
-                let ignore = experiment.ignore_mismatch_observation(&control, observation);
+            let comparison = experiment.compare_observations(control, observation);
+            if !comparison.is_equivalent() {
+                let ignore = experiment.ignore_mismatch_observation(control, observation);
                 if ignore {
                     ignored.push(i);
                 } else {
                     mismatched.push(i);
                 }
+                reasons.push((i, comparison.reasons().to_vec()));
             }
         }
 
+        for (i, mismatch_reasons) in reasons {
+            observations[i].mismatch_reasons = mismatch_reasons;
+        }
+
         return (mismatched, ignored);
     }
 }
 
+// diffs follow from the other fields, whether they have been computed yet or not
+impl<R: Serialize + PartialEq> PartialEq for ExperimentResult<R> {
+    fn eq(&self, other: &Self) -> bool {
+        return self.experiment_name == other.experiment_name
+            && self.observations == other.observations
+            && self.context == other.context
+            && self.control_index == other.control_index
+            && self.mismatched_indexes == other.mismatched_indexes
+            && self.ignored_indexes == other.ignored_indexes
+            && self.sampling_rate == other.sampling_rate
+            && self.input == other.input;
+    }
+}
+
+impl<R: Serialize> Serialize for ExperimentResult<R> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("ExperimentResult", 9)?;
+        state.serialize_field("experiment_name", &self.experiment_name)?;
+        state.serialize_field("observations", &self.observations)?;
+        state.serialize_field("context", &self.context)?;
+        state.serialize_field("control_index", &self.control_index)?;
+        state.serialize_field("mismatched_indexes", &self.mismatched_indexes)?;
+        state.serialize_field("ignored_indexes", &self.ignored_indexes)?;
+        state.serialize_field("sampling_rate", &self.sampling_rate)?;
+        state.serialize_field("diffs", self.diffs())?;
+        state.serialize_field("input", &self.input)?;
+        return state.end();
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use std::cell::RefCell;
-    use serde::Serialize;
+    use std::cell::{Cell, RefCell};
+    use serde::{Serialize, Serializer};
     use serde_json::json;
-    use crate::{Context, Experiment, ExperimentResult, Observation};
+    use crate::{diff::Difference, Context, Experiment, ExperimentResult, Observation};
 
     // TODO: split this test?
     #[test]
@@ -237,7 +408,7 @@ mod tests {
     #[test]
     fn should_partition_observations_into_mismatched_and_ignored_when_applicable() {
         let mut experiment = Experiment::default();
-        experiment.add_ignore(|control, candidate| { candidate.value == 2 });
+        experiment.add_ignore(|control, candidate| { candidate.value == Some(2) });
 
         let a = create_observation("a", 1);
         let b = create_observation("b", 2);
@@ -288,7 +459,89 @@ mod tests {
         assert_eq!(Context::from_value(json!({"foo": "bar"})).unwrap(), result.context);
     }
 
-    fn create_observation<R: Clone + PartialEq + Serialize>(
+    #[test]
+    fn should_diff_mismatched_observations_against_the_control() {
+        #[derive(Clone, PartialEq, Serialize)]
+        struct User {
+            name: &'static str,
+            roles: Vec<&'static str>,
+        }
+
+        let experiment = Experiment::default();
+        let a = create_observation("a", User { name: "sean", roles: vec!["admin"] });
+        let b = create_observation("b", User { name: "sean", roles: vec!["admin"] });
+        let c = create_observation("c", User { name: "Sean", roles: vec!["admin", "user"] });
+
+        let result = ExperimentResult::new(&experiment, vec![a, b, c], 0);
+
+        assert_eq!(None, result.diff("b"));
+        assert_eq!(
+            Some(&vec![
+                Difference::Changed { path: "/name".to_string(), from: json!("sean"), to: json!("Sean") },
+                Difference::Added { path: "/roles/1".to_string(), value: json!("user") },
+            ]),
+            result.diff("c")
+        );
+        assert_eq!(
+            json!({"c": [
+                {"op": "changed", "path": "/name", "from": "sean", "to": "Sean"},
+                {"op": "added", "path": "/roles/1", "value": "user"},
+            ]}),
+            serde_json::to_value(&result).unwrap()["diffs"]
+        );
+        assert_eq!(result.diffs(), result.erase().diffs());
+    }
+
+    #[test]
+    fn should_diff_cleaned_values_when_present() {
+        let experiment = Experiment::default();
+        let a = Observation::new("a".to_string(), "experiment".to_string(), 1, Some(json!({"v": "1"})), 1);
+        let b = Observation::new("b".to_string(), "experiment".to_string(), 2, Some(json!({"v": "2"})), 1);
+
+        let result = ExperimentResult::new(&experiment, vec![a, b], 0);
+
+        assert_eq!(
+            Some(&vec![Difference::Changed { path: "/v".to_string(), from: json!("1"), to: json!("2") }]),
+            result.diff("b")
+        );
+    }
+
+    #[test]
+    fn should_only_diff_observations_once_asked_to() {
+        struct Counted<'c> {
+            value: u32,
+            serializations: &'c Cell<usize>,
+        }
+
+        impl PartialEq for Counted<'_> {
+            fn eq(&self, other: &Self) -> bool {
+                return self.value == other.value;
+            }
+        }
+
+        impl Serialize for Counted<'_> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                self.serializations.set(self.serializations.get() + 1);
+                return self.value.serialize(serializer);
+            }
+        }
+
+        let serializations = Cell::new(0);
+        let experiment = Experiment::default();
+        let a = create_observation("a", Counted { value: 1, serializations: &serializations });
+        let b = create_observation("b", Counted { value: 2, serializations: &serializations });
+
+        let result = ExperimentResult::new(&experiment, vec![a, b], 0);
+        assert_eq!(0, serializations.get());
+
+        assert_eq!(1, result.diffs().len());
+        assert_eq!(2, serializations.get());
+
+        assert_eq!(1, result.diff("b").unwrap().len());
+        assert_eq!(2, serializations.get());
+    }
+
+    fn create_observation<R: Serialize>(
         name: &'static str,
         value: R
     ) -> Observation<R> {
@@ -0,0 +1,473 @@
+use std::{collections::HashMap, convert::Infallible, error::Error as StdError};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    context::Context,
+    errors::{BehaviorError, BehaviorNotUnique, ErrorAction, Operation, VictorsErrors, VictorsResult},
+    experiment::{Experiment, CONTROL_NAME, DEFAULT_CANDIDATE_NAME},
+    experiment_result::ExperimentResult,
+    observation::{Comparison, Observation},
+    result_publisher::Publisher,
+    sampling::Sampler,
+    unwind,
+};
+
+type BoxedBehavior<'a, I, R> = Box<dyn Fn(&I) -> Result<R, BehaviorError> + 'a>;
+type InputCleanerBlock<'a, I> = Box<dyn Fn(&I) -> VictorsResult<Value> + 'a>;
+
+/// An experiment whose behaviors take an input.
+///
+/// Rather than capturing their inputs, behaviors are given the input passed to
+/// [InputExperiment::run], so the experiment can be defined once and run for every request.
+/// Apart from that it has the same result, ignore, comparator and publisher semantics as
+/// [Experiment].
+pub struct InputExperiment<'a, I, R: Serialize> {
+    experiment: Experiment<'a, R>,
+    behaviors: HashMap<String, BoxedBehavior<'a, I, R>>,
+    input_cleaner: Option<InputCleanerBlock<'a, I>>,
+}
+
+impl<'a, I, R: PartialEq + Serialize> Default for InputExperiment<'a, I, R> {
+    /// Creates a new experiment with the name "experiment"
+    fn default() -> Self {
+        return InputExperiment::from_experiment(Experiment::default());
+    }
+}
+
+impl<'a, I, R: PartialEq + Serialize> InputExperiment<'a, I, R> {
+    /// Creates a new experiment whose values are compared with `==`
+    ///
+    /// # Arguments
+    /// * `name` - the name of the experiment
+    pub fn new(name: &'static str) -> Self {
+        return InputExperiment::from_experiment(Experiment::new(name));
+    }
+
+    /// Creates a new experiment with initial context
+    ///
+    /// # Arguments
+    /// * `name` - the name of the experiment
+    /// * `context` - Map of extra experiment data
+    pub fn new_with_context(name: &'static str, context: Context) -> Self {
+        return InputExperiment::from_experiment(Experiment::new_with_context(name, context));
+    }
+}
+
+impl<'a, I, R: Serialize> InputExperiment<'a, I, R> {
+    /// Creates a new experiment whose values are compared with the given comparator.
+    /// See [Experiment::with_comparator]
+    pub fn with_comparator<F>(name: &'static str, comparator: F) -> Self
+    where
+        F: Fn(&R, &R) -> bool + 'a,
+    {
+        return InputExperiment::from_experiment(Experiment::with_comparator(name, comparator));
+    }
+
+    fn from_experiment(experiment: Experiment<'a, R>) -> Self {
+        return Self {
+            experiment,
+            behaviors: Default::default(),
+            input_cleaner: None,
+        };
+    }
+
+    /// Returns the name of the experiment
+    pub fn name(&self) -> &String {
+        return &self.experiment.name;
+    }
+
+    /// Returns reference to the experiment context
+    pub fn context(&self) -> &Context {
+        return &self.experiment.context;
+    }
+
+    /// Define a block that determines whether or not the candidate experiments should run.
+    pub fn run_if<F>(&mut self, block: F)
+    where
+        F: Fn() -> bool + 'a,
+    {
+        self.experiment.run_if(block)
+    }
+
+    /// Register a candidate behavior for this experiment, defaults name to "candidate".
+    pub fn candidate<F>(&mut self, f: F) -> VictorsResult<()>
+    where
+        F: Fn(&I) -> R + 'a,
+    {
+        self.add_behavior(DEFAULT_CANDIDATE_NAME, move |input| Ok::<R, Infallible>(f(input)))
+    }
+
+    /// Register a named candidate behavior for this experiment.
+    pub fn candidate_with_name<F>(&mut self, name: &str, f: F) -> VictorsResult<()>
+    where
+        F: Fn(&I) -> R + 'a,
+    {
+        self.add_behavior(name, move |input| Ok::<R, Infallible>(f(input)))
+    }
+
+    /// Register the control behavior for this experiment.
+    pub fn control<F>(&mut self, f: F) -> VictorsResult<()>
+    where
+        F: Fn(&I) -> R + 'a,
+    {
+        self.add_behavior(CONTROL_NAME, move |input| Ok::<R, Infallible>(f(input)))
+    }
+
+    /// Register a fallible candidate behavior for this experiment, defaults name to "candidate".
+    /// See [Experiment::try_candidate]
+    pub fn try_candidate<F, E>(&mut self, f: F) -> VictorsResult<()>
+    where
+        F: Fn(&I) -> Result<R, E> + 'a,
+        E: Into<BehaviorError>,
+    {
+        self.add_behavior(DEFAULT_CANDIDATE_NAME, f)
+    }
+
+    /// Register a named fallible candidate behavior for this experiment.
+    pub fn try_candidate_with_name<F, E>(&mut self, name: &str, f: F) -> VictorsResult<()>
+    where
+        F: Fn(&I) -> Result<R, E> + 'a,
+        E: Into<BehaviorError>,
+    {
+        self.add_behavior(name, f)
+    }
+
+    /// Register a fallible control behavior for this experiment.
+    /// See [Experiment::try_control]
+    pub fn try_control<F, E>(&mut self, f: F) -> VictorsResult<()>
+    where
+        F: Fn(&I) -> Result<R, E> + 'a,
+        E: Into<BehaviorError>,
+    {
+        self.add_behavior(CONTROL_NAME, f)
+    }
+
+    fn add_behavior<F, E>(&mut self, name: &str, f: F) -> VictorsResult<()>
+    where
+        F: Fn(&I) -> Result<R, E> + 'a,
+        E: Into<BehaviorError>,
+    {
+        if self.behaviors.contains_key(name) {
+            return Err(VictorsErrors::BehaviorNotUnique(BehaviorNotUnique {
+                experiment_name: self.experiment.name.to_string(),
+                name: name.to_string(),
+            }));
+        }
+        self.behaviors.insert(name.to_string(), Box::new(move |input| f(input).map_err(Into::into)));
+
+        return Ok(());
+    }
+
+    /// Define a block of code to run before an experiment begins, if the experiment is enabled.
+    pub fn before_run<F>(&mut self, f: F)
+    where
+        F: Fn() + 'a,
+    {
+        self.experiment.before_run(f)
+    }
+
+    /// A block to clean an observed value for publishing or storing.
+    /// See [Experiment::clean]
+    pub fn clean<F, C>(&mut self, f: F)
+    where
+        F: Fn(&R) -> C + 'a,
+        C: Serialize,
+    {
+        self.experiment.clean(f)
+    }
+
+    /// A block to clean the input for publishing, so that mismatches can be replayed.
+    ///
+    /// The input is only included in published results, as [ExperimentResult::input], when this
+    /// block is set. It takes a reference to the input and returns the value to publish in its
+    /// place, for example the input itself with sensitive fields removed. Failures are handed to
+    /// the error handler as [Operation::Clean] and the input is left out.
+    pub fn clean_input<F, C>(&mut self, f: F)
+    where
+        F: Fn(&I) -> C + 'a,
+        C: Serialize,
+    {
+        self.input_cleaner = Some(Box::new(move |input| Ok(serde_json::to_value(f(input))?)))
+    }
+
+    /// See [Experiment::add_context]
+    pub fn add_context(&mut self, context: Context) {
+        self.experiment.add_context(context)
+    }
+
+    /// Configure experiment to ignore observations based on the given block.
+    /// See [Experiment::add_ignore]
+    pub fn add_ignore<F>(&mut self, ignore_block: F)
+    where
+        F: Fn(&Observation<R>, &Observation<R>) -> bool + 'a,
+    {
+        self.experiment.add_ignore(ignore_block)
+    }
+
+    /// See [Experiment::ignore_mismatch_observation]
+    pub fn ignore_mismatch_observation(&self, control: &Observation<R>, candidate: &Observation<R>) -> bool {
+        self.experiment.ignore_mismatch_observation(control, candidate)
+    }
+
+    /// See [Experiment::observations_are_equivalent]
+    pub fn observations_are_equivalent(&self, a: &Observation<R>, b: &Observation<R>) -> bool {
+        return self.experiment.observations_are_equivalent(a, b);
+    }
+
+    /// See [Experiment::enabled]
+    pub fn enabled<F>(&mut self, enabled: F)
+    where
+        F: Fn() -> bool + 'a,
+    {
+        self.experiment.enabled(enabled)
+    }
+
+    /// See [Experiment::is_enabled]
+    pub fn is_enabled(&self) -> bool {
+        return self.experiment.is_enabled();
+    }
+
+    /// See [Experiment::sampler]
+    pub fn sampler<S: Sampler + 'a>(&mut self, sampler: S) {
+        self.experiment.sampler(sampler)
+    }
+
+    /// See [Experiment::percent_enabled]
+    pub fn percent_enabled(&mut self, percent: f64) {
+        self.experiment.percent_enabled(percent)
+    }
+
+    /// See [Experiment::result_publisher]
+    pub fn result_publisher<T: Publisher<R> + 'a>(&mut self, publisher: T) {
+        self.experiment.result_publisher(publisher)
+    }
+
+    /// Decide what happens when an operation of the experiment fails. See [Experiment::on_error]
+    pub fn on_error<F>(&mut self, handler: F)
+    where
+        F: Fn(Operation, &VictorsErrors) -> ErrorAction + 'a,
+    {
+        self.experiment.on_error(handler)
+    }
+
+    /// See [Experiment::comparator]
+    pub fn comparator<F>(&mut self, comparator: F)
+    where
+        F: Fn(&R, &R) -> bool + 'a,
+    {
+        self.experiment.comparator(comparator)
+    }
+
+    /// See [Experiment::explaining_comparator]
+    pub fn explaining_comparator<F>(&mut self, comparator: F)
+    where
+        F: Fn(&R, &R) -> Comparison + 'a,
+    {
+        self.experiment.explaining_comparator(comparator)
+    }
+
+    /// See [Experiment::error_comparator]
+    pub fn error_comparator<F>(&mut self, comparator: F)
+    where
+        F: Fn(&String, &String) -> bool + 'a,
+    {
+        self.experiment.error_comparator(comparator)
+    }
+
+    /// Whether to return an error when the control and candidate mismatch.
+    /// See [Experiment::err_on_mismatches]
+    pub fn err_on_mismatches(&mut self, err_on_mismatches: bool) {
+        self.experiment.err_on_mismatches(err_on_mismatches)
+    }
+
+    /// A block which builds the error returned when the observations mismatch.
+    /// See [Experiment::raise_with]
+    pub fn raise_with<F, E>(&mut self, block: F)
+    where
+        F: Fn(&ExperimentResult<R>) -> E + 'a,
+        E: StdError + Send + Sync + 'static,
+    {
+        self.experiment.raise_with(block)
+    }
+
+    /// Same as [InputExperiment::run] but returns the caller's own error type.
+    /// See [Experiment::run_with_error]
+    pub fn run_with_error<E>(&mut self, input: &I) -> Result<R, E>
+    where
+        E: From<VictorsErrors> + StdError + 'static,
+    {
+        return self.run(input).map_err(VictorsErrors::into_error);
+    }
+
+    /// Run all the behaviors for this experiment with the given input, observing each and
+    /// publishing the results.
+    /// Return the result of the control
+    ///
+    /// # Arguments
+    /// * `input` - passed to every behavior
+    pub fn run(&mut self, input: &I) -> VictorsResult<R> {
+        let block = match self.behaviors.get(CONTROL_NAME) {
+            None => return Err(self.experiment.behavior_missing(CONTROL_NAME)),
+            Some(block) => block,
+        };
+
+        if !self.experiment.start_run(self.behaviors.len())? {
+            return block(input).map_err(|err| self.experiment.behavior_failed(CONTROL_NAME, err));
+        }
+
+        let (result, control_error) = self.experiment.run_behaviors_now(&self.behaviors, CONTROL_NAME, |key, behavior| {
+            // candidates are isolated so that a panicking candidate never takes the control down with it
+            return Some(if key == CONTROL_NAME { Ok(behavior(input)) } else { unwind::catch(|| behavior(input)) });
+        })?;
+
+        let cleaned_input = match &self.input_cleaner {
+            None => None,
+            Some(cleaner) => self.experiment.clean_with(cleaner, input),
+        };
+        return self.experiment.publish_result(CONTROL_NAME, result.with_input(cleaned_input), control_error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use serde::Serialize;
+    use serde_json::json;
+
+    use crate::{
+        errors::{ErrorAction, Operation, VictorsErrors},
+        result_publisher::InMemoryPublisher,
+        ExperimentResult, InputExperiment,
+    };
+
+    #[derive(Clone, Serialize)]
+    struct Request {
+        user_id: u32,
+        token: &'static str,
+    }
+
+    fn request(user_id: u32) -> Request {
+        return Request { user_id, token: "secret" };
+    }
+
+    #[test]
+    fn should_pass_the_input_to_every_behavior() {
+        let r: RefCell<Option<ExperimentResult<u32>>> = RefCell::new(None);
+
+        let mut experiment = InputExperiment::default();
+        experiment.control(|request: &Request| request.user_id * 2).unwrap();
+        experiment.candidate(|request: &Request| request.user_id + request.user_id).unwrap();
+        experiment.result_publisher(InMemoryPublisher::new(|result| {
+            r.replace(Some(result.clone()));
+        }));
+
+        assert_eq!(Ok(4), experiment.run(&request(2)));
+        assert!(r.take().unwrap().matched());
+        assert_eq!(Ok(10), experiment.run(&request(5)));
+        assert!(r.take().unwrap().matched());
+    }
+
+    #[test]
+    fn should_report_mismatches_for_an_input() {
+        let r: RefCell<Option<ExperimentResult<u32>>> = RefCell::new(None);
+
+        let mut experiment = InputExperiment::default();
+        experiment.control(|request: &Request| request.user_id * 2).unwrap();
+        experiment.candidate(|request: &Request| request.user_id * request.user_id).unwrap();
+        experiment.result_publisher(InMemoryPublisher::new(|result| {
+            r.replace(Some(result.clone()));
+        }));
+
+        assert_eq!(Ok(4), experiment.run(&request(2)));
+        assert!(r.take().unwrap().matched());
+        assert_eq!(Ok(6), experiment.run(&request(3)));
+        assert!(r.take().unwrap().has_mismatches());
+    }
+
+    #[test]
+    fn should_leave_the_input_out_of_results_by_default() {
+        let r: RefCell<Option<ExperimentResult<u32>>> = RefCell::new(None);
+
+        let mut experiment = InputExperiment::default();
+        experiment.control(|request: &Request| request.user_id).unwrap();
+        experiment.candidate(|request: &Request| request.user_id).unwrap();
+        experiment.result_publisher(InMemoryPublisher::new(|result| {
+            r.replace(Some(result.clone()));
+        }));
+
+        experiment.run(&request(1)).unwrap();
+
+        assert_eq!(None, r.take().unwrap().input());
+    }
+
+    #[test]
+    fn should_publish_the_cleaned_input() {
+        let r: RefCell<Option<ExperimentResult<u32>>> = RefCell::new(None);
+
+        let mut experiment = InputExperiment::default();
+        experiment.control(|request: &Request| request.user_id).unwrap();
+        experiment.candidate(|request: &Request| request.user_id + 1).unwrap();
+        experiment.clean_input(|request: &Request| json!({ "user_id": request.user_id }));
+        experiment.result_publisher(InMemoryPublisher::new(|result| {
+            r.replace(Some(result.clone()));
+        }));
+
+        experiment.run(&request(7)).unwrap();
+
+        let result = r.take().unwrap();
+        assert_eq!(Some(&json!({ "user_id": 7 })), result.input());
+        let published = serde_json::to_string(&result).unwrap();
+        assert!(published.contains(r#""input":{"user_id":7}"#));
+        assert!(!published.contains("secret"));
+        assert_eq!(result.input(), result.erase().input());
+    }
+
+    #[test]
+    fn should_report_errors_in_the_input_clean_block_and_leave_the_input_out() {
+        let reported = RefCell::new(vec![]);
+        let r: RefCell<Option<ExperimentResult<u32>>> = RefCell::new(None);
+
+        let mut experiment = InputExperiment::default();
+        experiment.control(|request: &Request| request.user_id).unwrap();
+        experiment.candidate(|request: &Request| request.user_id).unwrap();
+        experiment.clean_input(|_request: &Request| -> u32 { panic!("clean failed") });
+        experiment.on_error(|operation, _error| {
+            reported.borrow_mut().push(operation);
+            return ErrorAction::Swallow;
+        });
+        experiment.result_publisher(InMemoryPublisher::new(|result| {
+            r.replace(Some(result.clone()));
+        }));
+
+        assert_eq!(Ok(1), experiment.run(&request(1)));
+        assert_eq!(vec![Operation::Clean], *reported.borrow());
+        assert_eq!(None, r.take().unwrap().input());
+    }
+
+    #[test]
+    fn should_only_run_control_when_disabled() {
+        let candidate_ran = RefCell::new(false);
+
+        let mut experiment = InputExperiment::default();
+        experiment.control(|request: &Request| request.user_id).unwrap();
+        experiment.candidate(|request: &Request| {
+            candidate_ran.replace(true);
+            request.user_id
+        }).unwrap();
+        experiment.enabled(|| false);
+
+        assert_eq!(Ok(3), experiment.run(&request(3)));
+        assert!(!candidate_ran.take());
+    }
+
+    #[test]
+    fn should_return_error_when_control_is_missing() {
+        let mut experiment: InputExperiment<'_, Request, u32> = InputExperiment::new("missing");
+        experiment.candidate(|request| request.user_id).unwrap();
+
+        assert!(matches!(experiment.run(&request(1)), Err(VictorsErrors::BehaviorMissing(_))));
+    }
+}
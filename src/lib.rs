@@ -4,22 +4,34 @@
 #![feature(backtrace)]
 #![deny(elided_lifetimes_in_paths)]
 
+pub mod async_experiment;
+pub mod comparators;
 pub mod context;
+pub mod diff;
 pub mod errors;
 pub mod experiment;
 pub mod experiment_result;
+pub mod input_experiment;
 pub mod observation;
+pub mod parallel_experiment;
+pub mod publishers;
 pub mod result_publisher;
+pub mod sampling;
+mod timeout;
+mod unwind;
 pub mod victor;
 
 // TODO: can i use *?
 // https://github.com/SeaQL/sea-orm/blob/master/src/lib.rs
 pub use crate::{
+    async_experiment::AsyncExperiment,
     context::Context,
     experiment::{Experiment, UncontrolledExperiment},
     experiment_result::ExperimentResult,
-    observation::Observation,
-    result_publisher::Publisher,
+    input_experiment::InputExperiment,
+    observation::{Comparison, Observation},
+    parallel_experiment::ParallelExperiment,
+    result_publisher::{Publisher, ScientistValue},
 };
 
 #[cfg(test)]
@@ -27,25 +39,28 @@ mod tests {
     use bincode;
     use std::{
         cell::{RefCell},
+        sync::{Arc, Mutex},
     };
     use std::cell::Ref;
     use std::collections::HashSet;
-    use serde::Serialize;
+    use serde::{Deserialize, Serialize};
 
     use serde_json::{json, Value};
 
     use crate::{
         context::Context,
         errors::{
-            BehaviorMissing, BehaviorNotUnique, VictorsErrors, VictorsResult
+            BehaviorFailed, BehaviorMissing, BehaviorNotUnique, ErrorAction, MismatchError, Operation, OperationFailed,
+            PublishError, VictorsErrors, VictorsResult
         },
         experiment::Experiment,
         experiment_result::ExperimentResult,
-        observation::Observation,
+        observation::{Comparison, Observation, ObservationState},
         Publisher,
-        result_publisher::InMemoryPublisher,
+        result_publisher::{InMemoryPublisher, NoopPublisher, ScientistValue},
+        sampling::Sampler,
         UncontrolledExperiment,
-        victor::Victor
+        victor::{self, Victor}
     };
     use crate::victor::Scientist;
 
@@ -53,14 +68,15 @@ mod tests {
     fn try_new_dr() {
 
         pub struct PrintPublisher;
-        impl<R: Clone + PartialEq + Serialize> Publisher<R> for PrintPublisher {
-            fn publish(&self, result: &ExperimentResult<R>) {
-                println!("{}", serde_json::to_string(result).unwrap());
+        impl<R: Serialize> Publisher<R> for PrintPublisher {
+            fn publish(&self, result: &ExperimentResult<R>) -> Result<(), PublishError> {
+                println!("{}", serde_json::to_string(result)?);
+                return Ok(());
             }
         }
 
         struct Reed;
-        impl<'a, R: Clone + PartialEq + Serialize> Scientist<'a, R> for Reed {
+        impl<'a, R: Serialize> Scientist<'a, R> for Reed {
             type P = PrintPublisher;
 
             fn get_publisher() -> Self::P {
@@ -154,6 +170,50 @@ mod tests {
         assert!(!called.take());
     }
 
+    #[test]
+    fn should_not_run_candidates_when_not_sampled() {
+        let called = RefCell::new(false);
+
+        let mut experiment = Experiment::default();
+        experiment.control(|| 1).unwrap();
+        experiment.candidate(|| {
+            called.replace(true);
+            1
+        }).unwrap();
+        experiment.percent_enabled(0.0);
+
+        assert_eq!(1, experiment.run().unwrap());
+        assert!(!called.take());
+    }
+
+    #[test]
+    fn should_record_sampling_rate_on_published_result() {
+        struct EveryTenthRun;
+        impl Sampler for EveryTenthRun {
+            fn sample(&self, _experiment_name: &str, _context: &Context) -> bool {
+                true
+            }
+
+            fn rate(&self) -> f64 {
+                0.1
+            }
+        }
+
+        let r: RefCell<Option<ExperimentResult<u8>>> = RefCell::new(None);
+
+        let mut experiment = Experiment::default();
+        experiment.control(|| 1).unwrap();
+        experiment.candidate(|| 1).unwrap();
+        experiment.sampler(EveryTenthRun);
+        experiment.result_publisher(InMemoryPublisher::new(|result| {
+            r.replace(Some(result.clone()));
+        }));
+
+        experiment.run().unwrap();
+
+        assert_eq!(0.1, r.take().unwrap().sampling_rate());
+    }
+
     #[test]
     fn should_allow_to_init_experiment_with_context() {
         let r: RefCell<Option<ExperimentResult<u8>>> = RefCell::new(None);
@@ -243,7 +303,6 @@ mod tests {
         assert!(called.take());
     }
 
-    // TODO: parallel
 
     #[test]
     fn should_be_able_to_create_and_run_experiment_via_victor() {
@@ -259,6 +318,46 @@ mod tests {
         assert_eq!(Some(1), r.ok());
     }
 
+    #[test]
+    fn should_publish_to_global_publisher() {
+        struct SharedPublisher(Arc<Mutex<Vec<ExperimentResult<ScientistValue>>>>);
+        impl Publisher<ScientistValue> for SharedPublisher {
+            fn publish(&self, result: &ExperimentResult<ScientistValue>) -> Result<(), PublishError> {
+                // other tests run in parallel and publish to the global publisher too
+                if result.experiment_name() == "global publisher test" {
+                    self.0.lock().unwrap().push(result.clone());
+                }
+                return Ok(());
+            }
+        }
+
+        let published = Arc::new(Mutex::new(vec![]));
+        let previous = victor::set_publisher(SharedPublisher(Arc::clone(&published)));
+
+        let value = Victor::conduct("global publisher test", |experiment| {
+            experiment.control(|| vec![1, 2])?;
+            experiment.candidate(|| vec![1, 3])?;
+            Ok(())
+        });
+        let mut experiment = Experiment::new("global publisher test");
+        experiment.control(|| "control".to_string()).unwrap();
+        experiment.candidate(|| "control".to_string()).unwrap();
+        experiment.run().unwrap();
+
+        victor::set_publisher(previous);
+
+        assert_eq!(vec![1, 2], value.unwrap());
+        let published = published.lock().unwrap();
+        assert_eq!(2, published.len());
+        assert!(published[0].has_mismatches());
+        assert_eq!(
+            Some(ScientistValue::from(json!([1, 2]))),
+            published[0].control().unwrap().value
+        );
+        assert!(published[1].matched());
+        assert_eq!(Some("control"), published[1].control().unwrap().value.as_ref().unwrap().as_str());
+    }
+
     #[test]
     fn should_be_able_to_create_and_run_uncontrolled_experiment_via_victor() {
         let r = Victor::conduct_uncontrolled("uncontrolled test", "second", |experiment| {
@@ -273,6 +372,47 @@ mod tests {
         assert_eq!(Some(2), r.ok());
     }
 
+    #[test]
+    fn should_err_on_mismatches_when_scientist_defaults_to_it() {
+        struct Strict;
+        impl<'a, R: Serialize> Scientist<'a, R> for Strict {
+            type P = NoopPublisher;
+
+            fn get_publisher() -> Self::P {
+                return NoopPublisher;
+            }
+
+            fn err_on_mismatches() -> bool {
+                return true;
+            }
+        }
+
+        let strict = Strict::conduct("strict", |experiment| {
+            experiment.control(|| 1)?;
+            experiment.candidate(|| 2)?;
+            Ok(())
+        });
+        let relaxed = Strict::conduct("relaxed", |experiment| {
+            experiment.control(|| 1)?;
+            experiment.candidate(|| 2)?;
+            experiment.err_on_mismatches(false);
+            Ok(())
+        });
+
+        assert!(matches!(strict, Err(VictorsErrors::MismatchError(_))));
+        assert_eq!(Ok(1), relaxed);
+    }
+
+    #[test]
+    fn should_err_on_mismatches_when_enabled_on_uncontrolled_experiment() {
+        let mut experiment = UncontrolledExperiment::new("uncontrolled");
+        experiment.candidate("first", || 1).unwrap();
+        experiment.candidate("second", || 2).unwrap();
+        experiment.err_on_mismatches(true);
+
+        assert!(matches!(experiment.run("first"), Err(VictorsErrors::MismatchError(_))));
+    }
+
     // ignore ignore_mismatched_observation tests
     // TODO: does not ignore an observation if no ignores are configured
     // TODO: calls a configured ignore block with the given observed values
@@ -333,11 +473,149 @@ mod tests {
         assert_eq!(expected, result.unwrap_err());
     }
 
-    // TODO: swallows exceptions raised by candidate behaviors
+    #[test]
+    fn should_swallow_errors_returned_by_candidate_behaviors() {
+        let r: RefCell<Option<ExperimentResult<u8>>> = RefCell::new(None);
 
-    // TODO: re-raises exceptions raised during publish by default
-    // TODO: reports publishing errors
-    // TODO: publishes results
+        let mut experiment = Experiment::default();
+        experiment.control(|| 1).unwrap();
+        experiment.try_candidate(|| Err("candidate failed")).unwrap();
+        experiment.result_publisher(InMemoryPublisher::new(|result| {
+            r.replace(Some(result.clone()));
+        }));
+
+        let value = experiment.run().unwrap();
+
+        assert_eq!(1, value);
+        let result = r.take().unwrap();
+        assert!(result.has_mismatches());
+        let candidate = result.mismatched()[0];
+        assert!(candidate.raised());
+        assert_eq!(None, candidate.value);
+        assert_eq!(Some("candidate failed".to_string()), candidate.exception);
+    }
+
+    #[test]
+    fn should_swallow_panics_raised_by_candidate_behaviors() {
+        let r: RefCell<Option<ExperimentResult<u8>>> = RefCell::new(None);
+
+        let mut experiment = Experiment::default();
+        experiment.control(|| 1).unwrap();
+        experiment.candidate(|| panic!("candidate exploded")).unwrap();
+        experiment.result_publisher(InMemoryPublisher::new(|result| {
+            r.replace(Some(result.clone()));
+        }));
+
+        let value = experiment.run().unwrap();
+
+        assert_eq!(1, value);
+        let result = r.take().unwrap();
+        assert!(result.has_mismatches());
+        let candidate = result.mismatched()[0];
+        assert!(candidate.panicked());
+        assert_eq!(ObservationState::Panicked, candidate.state);
+        assert_eq!(None, candidate.value);
+        assert_eq!(Some("candidate exploded".to_string()), candidate.exception);
+    }
+
+    #[test]
+    fn should_count_each_panicking_candidate_as_a_mismatch() {
+        let r: RefCell<Option<ExperimentResult<u8>>> = RefCell::new(None);
+
+        let mut experiment = UncontrolledExperiment::default();
+        experiment.candidate("first", || 1).unwrap();
+        experiment.candidate("second", || panic!("boom")).unwrap();
+        experiment.candidate("third", || panic!("boom")).unwrap();
+        experiment.result_publisher(InMemoryPublisher::new(|result| {
+            r.replace(Some(result.clone()));
+        }));
+
+        assert_eq!(1, experiment.run("first").unwrap());
+        assert_eq!(2, r.take().unwrap().mismatched().len());
+    }
+
+    #[test]
+    fn should_return_error_returned_by_control_behavior() {
+        let r: RefCell<Option<ExperimentResult<u8>>> = RefCell::new(None);
+
+        let mut experiment = Experiment::default();
+        experiment.try_control(|| Err("control failed")).unwrap();
+        experiment.candidate(|| 1).unwrap();
+        experiment.result_publisher(InMemoryPublisher::new(|result| {
+            r.replace(Some(result.clone()));
+        }));
+
+        let result = experiment.run();
+
+        let expected = VictorsErrors::BehaviorFailed(BehaviorFailed {
+            experiment_name: "experiment".to_string(),
+            name: "control".to_string(),
+            source: "control failed".into(),
+        });
+        assert_eq!(expected, result.unwrap_err());
+        assert_eq!(Some("control failed".to_string()), r.take().unwrap().control().unwrap().exception);
+    }
+
+    #[test]
+    fn should_return_error_returned_by_control_behavior_when_experiment_is_disabled() {
+        let mut experiment: Experiment<'_, u8> = Experiment::default();
+        experiment.try_control(|| Err("control failed")).unwrap();
+        experiment.candidate(|| 1).unwrap();
+        experiment.enabled(|| false);
+
+        let result = experiment.run();
+
+        assert!(matches!(result, Err(VictorsErrors::BehaviorFailed(_))));
+    }
+
+    struct FailingPublisher;
+    impl Publisher<u8> for FailingPublisher {
+        fn publish(&self, _result: &ExperimentResult<u8>) -> Result<(), PublishError> {
+            return Err("publisher unavailable".into());
+        }
+    }
+
+    fn failing_publisher_experiment<'a>() -> Experiment<'a, u8> {
+        let mut experiment = Experiment::default();
+        experiment.control(|| 1).unwrap();
+        experiment.candidate(|| 1).unwrap();
+        experiment.result_publisher(FailingPublisher);
+        return experiment;
+    }
+
+    #[test]
+    fn should_return_control_value_when_publishing_fails_by_default() {
+        let mut experiment = failing_publisher_experiment();
+
+        assert_eq!(Ok(1), experiment.run());
+    }
+
+    #[test]
+    fn should_report_publishing_errors_to_error_handler() {
+        let reported: RefCell<Vec<(Operation, String)>> = RefCell::new(vec![]);
+
+        let mut experiment = failing_publisher_experiment();
+        experiment.on_error(|operation, error| {
+            reported.borrow_mut().push((operation, error.to_string()));
+            return ErrorAction::Swallow;
+        });
+
+        assert_eq!(Ok(1), experiment.run());
+        assert_eq!(
+            vec![(Operation::Publish, "experiment failed to publish result: publisher unavailable".to_string())],
+            reported.take()
+        );
+    }
+
+    #[test]
+    fn should_return_publishing_error_when_error_handler_raises_it() {
+        let mut experiment = failing_publisher_experiment();
+        experiment.on_error(|_operation, _error| ErrorAction::Raise);
+
+        let result = experiment.run();
+
+        assert!(matches!(result, Err(VictorsErrors::PublishFailed(_))));
+    }
 
 
     #[test]
@@ -358,8 +636,39 @@ mod tests {
         assert!(r.take().is_none());
     }
 
-    // TODO: compares results with a comparator block if provided
-    // TODO: compares errors with an error comparator block if provided
+    #[test]
+    fn should_compare_errors_with_error_comparator_when_provided() {
+        let r: RefCell<Option<ExperimentResult<u8>>> = RefCell::new(None);
+
+        let mut experiment = Experiment::default();
+        experiment.try_control(|| Err("connection refused: 10.0.0.1")).unwrap();
+        experiment.try_candidate(|| Err("connection refused: 10.0.0.2")).unwrap();
+        experiment.error_comparator(|a, b| a.split(':').next() == b.split(':').next());
+        experiment.result_publisher(InMemoryPublisher::new(|result| {
+            r.replace(Some(result.clone()));
+        }));
+
+        assert!(experiment.run().is_err());
+        assert!(r.take().unwrap().matched());
+    }
+
+    #[test]
+    fn should_mismatch_when_only_one_behavior_returns_an_error() {
+        let r: RefCell<Option<ExperimentResult<u8>>> = RefCell::new(None);
+
+        let mut experiment = Experiment::default();
+        experiment.control(|| 1).unwrap();
+        experiment.try_candidate(|| Err("candidate failed")).unwrap();
+        experiment.error_comparator(|_a, _b| true);
+        experiment.result_publisher(InMemoryPublisher::new(|result| {
+            r.replace(Some(result.clone()));
+        }));
+
+        experiment.run().unwrap();
+
+        assert!(r.take().unwrap().has_mismatches());
+    }
+
     #[test]
     fn should_compare_results_with_comparator_when_provided() {
         #[derive(Clone, PartialEq, Serialize)]
@@ -390,39 +699,458 @@ mod tests {
         assert!(r.take().unwrap().matched());
     }
 
+    #[test]
+    fn should_run_experiments_over_types_without_partial_eq_with_a_comparator() {
+        #[derive(Clone, Debug, Serialize)]
+        struct Reading {
+            celsius: f64,
+        }
+
+        let r: RefCell<Option<ExperimentResult<Reading>>> = RefCell::new(None);
+
+        let mut experiment = Experiment::with_comparator("readings", |a: &Reading, b: &Reading| {
+            (a.celsius - b.celsius).abs() < 0.01
+        });
+        experiment.control(|| Reading { celsius: 20.0 }).unwrap();
+        experiment.candidate(|| Reading { celsius: 20.001 }).unwrap();
+        experiment.candidate_with_name("broken", || Reading { celsius: 68.0 }).unwrap();
+        experiment.result_publisher(InMemoryPublisher::new(|result| {
+            r.replace(Some(result.clone()));
+        }));
+
+        assert_eq!(20.0, experiment.run().unwrap().celsius);
+
+        let result = r.take().unwrap();
+        assert_eq!(1, result.mismatched().len());
+        assert_eq!("broken", result.mismatched()[0].name);
+    }
+
+    #[test]
+    fn should_move_the_control_value_back_without_cloning() {
+        // deliberately neither Clone nor PartialEq
+        #[derive(Debug, Serialize)]
+        struct Response {
+            body: Vec<u8>,
+        }
+
+        let matched = RefCell::new(false);
+
+        let mut experiment = Experiment::with_comparator("response", |a: &Response, b: &Response| a.body == b.body);
+        experiment.control(|| Response { body: vec![1, 2, 3] }).unwrap();
+        experiment.candidate(|| Response { body: vec![1, 2, 3] }).unwrap();
+        experiment.result_publisher(InMemoryPublisher::new(|result: &ExperimentResult<Response>| {
+            matched.replace(result.matched());
+        }));
+
+        assert_eq!(vec![1, 2, 3], experiment.run().unwrap().body);
+        assert!(matched.take());
+    }
+
+    #[test]
+    fn should_compare_with_comparators_capturing_their_configuration() {
+        let r: RefCell<Option<ExperimentResult<Vec<f64>>>> = RefCell::new(None);
+        let tolerance = 0.01_f64;
+        let error_prefix = "timeout".to_string();
+
+        let mut experiment = Experiment::default();
+        experiment.control(|| vec![1.0, 2.0]).unwrap();
+        experiment.candidate(|| vec![1.001, 2.001]).unwrap();
+        experiment.comparator(|a: &Vec<f64>, b: &Vec<f64>| {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| (a - b).abs() <= tolerance)
+        });
+        experiment.error_comparator(move |a, b| a.starts_with(&error_prefix) && b.starts_with(&error_prefix));
+        experiment.result_publisher(InMemoryPublisher::new(|result| {
+            r.replace(Some(result.clone()));
+        }));
+
+        experiment.run().unwrap();
+        assert!(r.take().unwrap().matched());
+    }
+
+    #[test]
+    fn should_use_enabled_block_capturing_state() {
+        let candidate_ran = RefCell::new(false);
+        let staff = false;
+
+        let mut experiment = Experiment::default();
+        experiment.control(|| 1).unwrap();
+        experiment.candidate(|| {
+            candidate_ran.replace(true);
+            1
+        }).unwrap();
+        experiment.enabled(move || staff);
+
+        assert_eq!(Ok(1), experiment.run());
+        assert!(!candidate_ran.take());
+    }
+
+    #[test]
+    fn should_record_why_values_differ_with_explaining_comparator() {
+        let r: RefCell<Option<ExperimentResult<(u8, u8)>>> = RefCell::new(None);
+
+        let mut experiment = Experiment::default();
+        experiment.control(|| (1, 2)).unwrap();
+        experiment.candidate(|| (1, 3)).unwrap();
+        experiment.candidate_with_name("matching", || (1, 2)).unwrap();
+        experiment.explaining_comparator(|a, b| {
+            if a == b {
+                return Comparison::Equivalent;
+            }
+            return Comparison::different(format!("second element {} != {}", a.1, b.1));
+        });
+        experiment.result_publisher(InMemoryPublisher::new(|result| {
+            r.replace(Some(result.clone()));
+        }));
+
+        assert_eq!(Ok((1, 2)), experiment.run());
+
+        let result = r.take().unwrap();
+        assert_eq!(vec!["second element 2 != 3".to_string()], result.mismatched()[0].mismatch_reasons);
+        assert!(result.control().unwrap().mismatch_reasons.is_empty());
+        let published = serde_json::to_value(&result).unwrap();
+        let candidate = published["observations"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|observation| observation["name"] == "candidate")
+            .unwrap();
+        assert_eq!(json!(["second element 2 != 3"]), candidate["mismatch_reasons"]);
+    }
+
+    #[test]
+    fn should_show_mismatch_reasons_in_mismatch_error() {
+        let mut experiment = Experiment::new("mismatch");
+        experiment.control(|| "a".to_string()).unwrap();
+        experiment.candidate(|| "B".to_string()).unwrap();
+        experiment.explaining_comparator(|a, b| {
+            if a == b {
+                return Comparison::Equivalent;
+            }
+            return Comparison::Different(vec!["different letter".to_string(), "different case".to_string()]);
+        });
+
+        assert_eq!(
+            "experiment 'mismatch' observations mismatched:\ncontrol:\n  \"a\"\ncandidate:\n  \"B\"\n  reason: different letter\n  reason: different case\n",
+            mismatch_error(experiment).to_string()
+        );
+    }
+
 
     // TODO: knows how to compare two experiments
     // TODO: uses a compare block to determine if observations are equivalent
-    // TODO: reports errors in a compare block
-    // TODO: reports errors in the enabled? method
-    // TODO: reports errors in a run_if block
-    // TODO: returns the given value when no clean block is configured
-    // TODO: calls the configured clean block with a value when configured
-    // TODO: reports an error and returns the original value when an error is raised in a clean block
 
-    // TODO: calls multiple ignore blocks to see if any match
-    // TODO: only calls ignore blocks until one matches
-    // TODO: reports exceptions raised in an ignore block and returns false
-    // TODO: skips ignore blocks that raise and tests any remaining blocks if an exception is swallowed
+    fn reporting_experiment<'a>(reported: &'a RefCell<Vec<Operation>>) -> Experiment<'a, u8> {
+        let mut experiment = Experiment::default();
+        experiment.control(|| 1).unwrap();
+        experiment.candidate(|| 1).unwrap();
+        experiment.on_error(move |operation, error| {
+            assert!(matches!(error, VictorsErrors::OperationFailed(_)));
+            reported.borrow_mut().push(operation);
+            return ErrorAction::Swallow;
+        });
+        return experiment;
+    }
+
+    #[test]
+    fn should_report_errors_in_a_compare_block_and_mismatch() {
+        let reported = RefCell::new(vec![]);
+        let r: RefCell<Option<ExperimentResult<u8>>> = RefCell::new(None);
+
+        let mut experiment = reporting_experiment(&reported);
+        experiment.comparator(|_a, _b| panic!("compare failed"));
+        experiment.result_publisher(InMemoryPublisher::new(|result| {
+            r.replace(Some(result.clone()));
+        }));
+
+        assert_eq!(Ok(1), experiment.run());
+        assert!(reported.borrow().contains(&Operation::Compare));
+        assert!(r.take().unwrap().has_mismatches());
+    }
+
+    #[test]
+    fn should_report_errors_in_the_enabled_block_and_not_run_candidates() {
+        let reported = RefCell::new(vec![]);
+        let candidate_ran = RefCell::new(false);
+
+        let mut experiment = reporting_experiment(&reported);
+        experiment.candidate_with_name("second", || {
+            candidate_ran.replace(true);
+            1
+        }).unwrap();
+        experiment.enabled(|| panic!("enabled failed"));
+
+        assert_eq!(Ok(1), experiment.run());
+        assert_eq!(vec![Operation::Enabled], reported.take());
+        assert!(!*candidate_ran.borrow());
+    }
+
+    #[test]
+    fn should_report_errors_in_a_run_if_block_and_not_run_candidates() {
+        let reported = RefCell::new(vec![]);
+        let candidate_ran = RefCell::new(false);
+
+        let mut experiment = reporting_experiment(&reported);
+        experiment.candidate_with_name("second", || {
+            candidate_ran.replace(true);
+            1
+        }).unwrap();
+        experiment.run_if(|| panic!("run_if failed"));
+
+        assert_eq!(Ok(1), experiment.run());
+        assert_eq!(vec![Operation::RunIf], reported.take());
+        assert!(!*candidate_ran.borrow());
+    }
 
-    // TODO: raising on mismatches
-    // TODO: "raises when there is a mismatch if raise on mismatches is enabled"
-    // TODO: "cleans values when raising on observation mismatch"
-    // TODO: "doesn't raise when there is a mismatch if raise on mismatches is disabled"
-    // TODO: "raises a mismatch error if the control raises and candidate doesn't"
-    // TODO: "raises a mismatch error if the candidate raises and the control doesn't"
+    #[test]
+    fn should_report_errors_in_a_before_run_block_and_not_run_candidates() {
+        let reported = RefCell::new(vec![]);
+        let candidate_ran = RefCell::new(false);
 
+        let mut experiment = reporting_experiment(&reported);
+        experiment.candidate_with_name("second", || {
+            candidate_ran.replace(true);
+            1
+        }).unwrap();
+        experiment.before_run(|| panic!("before_run failed"));
 
+        assert_eq!(Ok(1), experiment.run());
+        assert_eq!(vec![Operation::BeforeRun], reported.take());
+        assert!(!*candidate_ran.borrow());
+    }
 
-    // TODO: raise_on_mismatches
-    // TODO: raises when there is a mismatch if the experiment instance's raise on mismatches is enabled
-    // TODO: doesn't raise when there is a mismatch if the experiment instance's raise on mismatches is disabled
+    #[test]
+    fn should_report_errors_in_a_clean_block_and_publish_the_raw_value() {
+        let reported = RefCell::new(vec![]);
+        let r: RefCell<Option<ExperimentResult<u8>>> = RefCell::new(None);
 
-    // TODO: MismatchError
-    // TODO: has the name of the experiment
-    // TODO: includes the experiments' results
-    // TODO: formats nicely as a string
-    // TODO: includes the backtrace when an observation raises
+        let mut experiment = reporting_experiment(&reported);
+        experiment.clean(|_value: &u8| -> u8 { panic!("clean failed") });
+        experiment.result_publisher(InMemoryPublisher::new(|result| {
+            r.replace(Some(result.clone()));
+        }));
+
+        assert_eq!(Ok(1), experiment.run());
+        assert_eq!(vec![Operation::Clean, Operation::Clean], reported.take());
+        let control = r.take().unwrap().control().unwrap().clone();
+        assert_eq!(None, control.cleaned_value);
+        assert_eq!(Some(1), control.value);
+    }
+
+    #[test]
+    fn should_skip_ignore_blocks_that_raise_and_test_remaining_blocks() {
+        let reported = RefCell::new(vec![]);
+        let r: RefCell<Option<ExperimentResult<u8>>> = RefCell::new(None);
+
+        let mut experiment = reporting_experiment(&reported);
+        experiment.candidate_with_name("second", || 2).unwrap();
+        experiment.add_ignore(|_a, _b| panic!("ignore failed"));
+        experiment.add_ignore(|_a, b| b.value == Some(2));
+        experiment.result_publisher(InMemoryPublisher::new(|result| {
+            r.replace(Some(result.clone()));
+        }));
+
+        assert_eq!(Ok(1), experiment.run());
+        assert_eq!(vec![Operation::Ignore], reported.take());
+        let result = r.take().unwrap();
+        assert!(!result.has_mismatches());
+        assert!(result.has_ignores());
+    }
+
+    #[test]
+    fn should_return_block_error_when_error_handler_raises_it() {
+        let published = RefCell::new(false);
+
+        let mut experiment = Experiment::default();
+        experiment.control(|| 1).unwrap();
+        experiment.candidate(|| 2).unwrap();
+        experiment.add_ignore(|_a, _b| panic!("ignore failed"));
+        experiment.on_error(|_operation, _error| ErrorAction::Raise);
+        experiment.result_publisher(InMemoryPublisher::new(|_result| {
+            published.replace(true);
+        }));
+
+        let result = experiment.run();
+
+        assert!(matches!(
+            result,
+            Err(VictorsErrors::OperationFailed(OperationFailed { operation: Operation::Ignore, .. }))
+        ));
+        assert!(!*published.borrow());
+    }
+
+    fn mismatch_error<'a>(mut experiment: Experiment<'a, String>) -> MismatchError {
+        experiment.err_on_mismatches = true;
+        return match experiment.run() {
+            Err(VictorsErrors::MismatchError(error)) => *error,
+            other => panic!("expected a mismatch error but got {:?}", other),
+        };
+    }
+
+    #[test]
+    fn should_raise_when_there_is_a_mismatch_if_raise_on_mismatches_is_enabled() {
+        let mut experiment = Experiment::new("mismatch");
+        experiment.control(|| "control".to_string()).unwrap();
+        experiment.candidate(|| "candidate".to_string()).unwrap();
+
+        let error = mismatch_error(experiment);
+
+        assert_eq!("mismatch", error.experiment_name);
+        assert_eq!(
+            Some("control"),
+            error.result.control().unwrap().value.as_ref().unwrap().as_str()
+        );
+        assert_eq!(
+            Some("candidate"),
+            error.result.mismatched()[0].value.as_ref().unwrap().as_str()
+        );
+    }
+
+    #[test]
+    fn should_not_raise_when_there_is_a_mismatch_if_raise_on_mismatches_is_disabled() {
+        let mut experiment = Experiment::default();
+        experiment.control(|| "control".to_string()).unwrap();
+        experiment.candidate(|| "candidate".to_string()).unwrap();
+
+        assert_eq!(Ok("control".to_string()), experiment.run());
+    }
+
+    #[test]
+    fn should_raise_a_mismatch_error_if_only_one_behavior_fails() {
+        let mut control_fails = Experiment::new("control fails");
+        control_fails.try_control(|| Err("control failed")).unwrap();
+        control_fails.candidate(|| "candidate".to_string()).unwrap();
+        let mut candidate_fails = Experiment::new("candidate fails");
+        candidate_fails.control(|| "control".to_string()).unwrap();
+        candidate_fails.try_candidate(|| Err("candidate failed")).unwrap();
+
+        assert!(mismatch_error(control_fails).to_string().contains("  error: control failed"));
+        assert!(mismatch_error(candidate_fails).to_string().contains("  error: candidate failed"));
+    }
+
+    #[test]
+    fn should_clean_values_when_raising_on_observation_mismatch() {
+        let mut experiment = Experiment::new("mismatch");
+        experiment.control(|| "secret-control".to_string()).unwrap();
+        experiment.candidate(|| "secret-candidate".to_string()).unwrap();
+        experiment.clean(|value: &String| value.len());
+
+        let error = mismatch_error(experiment);
+
+        assert_eq!(
+            "experiment 'mismatch' observations mismatched:\ncontrol:\n  14\ncandidate:\n  16\n",
+            error.to_string()
+        );
+        assert!(!serde_json::to_string(&error.result).unwrap().contains("secret"));
+    }
+
+    #[test]
+    fn should_not_keep_raw_values_of_cleaned_observations_in_mismatch_error() {
+        let mut experiment = Experiment::new("mismatch");
+        experiment.control(|| "secret-control".to_string()).unwrap();
+        experiment.candidate(|| "secret-candidate".to_string()).unwrap();
+        experiment.clean(|value: &String| value.len());
+
+        let error = mismatch_error(experiment);
+
+        assert!(!format!("{:?}", error).contains("secret"));
+        assert!(error.result.observations().iter().all(|observation| observation.value.is_none()));
+    }
+
+    #[test]
+    fn should_format_mismatch_error_nicely() {
+        let mut experiment = Experiment::new("mismatch");
+        experiment.control(|| "control".to_string()).unwrap();
+        experiment.candidate(|| "candidate".to_string()).unwrap();
+        experiment.candidate_with_name("second", || panic!("candidate panicked")).unwrap();
+        experiment.candidate_with_name("matching", || "control".to_string()).unwrap();
+
+        let message = mismatch_error(experiment).to_string();
+
+        assert!(message.starts_with("experiment 'mismatch' observations mismatched:\ncontrol:\n  \"control\"\n"));
+        assert!(message.contains("candidate:\n  \"candidate\"\n"));
+        assert!(message.contains("second:\n  panicked: candidate panicked\n"));
+        assert!(!message.contains("matching"));
+    }
+
+    #[derive(Debug, thiserror::Error)]
+    enum AppError {
+        #[error("widgets differ: expected {expected} but got {actual}")]
+        WidgetsDiffer { expected: String, actual: String },
+        #[error("widget store unavailable")]
+        StoreUnavailable,
+        #[error(transparent)]
+        Victors(#[from] VictorsErrors),
+    }
+
+    fn custom_mismatch_experiment<'a>() -> Experiment<'a, String> {
+        let mut experiment = Experiment::new("custom mismatch");
+        experiment.control(|| "control".to_string()).unwrap();
+        experiment.candidate(|| "candidate".to_string()).unwrap();
+        experiment.err_on_mismatches = true;
+        experiment.raise_with(|result| AppError::WidgetsDiffer {
+            expected: result.control().unwrap().value.clone().unwrap(),
+            actual: result.mismatched()[0].value.clone().unwrap(),
+        });
+        return experiment;
+    }
+
+    #[test]
+    fn should_raise_custom_mismatch_error() {
+        let mut experiment = custom_mismatch_experiment();
+
+        let error = experiment.run().unwrap_err();
+
+        assert!(matches!(error, VictorsErrors::CustomMismatch(_)));
+        assert_eq!("widgets differ: expected control but got candidate", error.to_string());
+    }
+
+    #[test]
+    fn should_return_custom_mismatch_error_as_callers_error_type() {
+        let mut experiment = custom_mismatch_experiment();
+
+        let error = experiment.run_with_error::<AppError>().unwrap_err();
+
+        assert!(matches!(
+            error,
+            AppError::WidgetsDiffer { ref expected, ref actual } if expected == "control" && actual == "candidate"
+        ));
+    }
+
+    #[test]
+    fn should_return_control_error_as_callers_error_type() {
+        let mut experiment: Experiment<'_, String> = Experiment::default();
+        experiment.try_control(|| Err(AppError::StoreUnavailable)).unwrap();
+        experiment.candidate(|| "candidate".to_string()).unwrap();
+
+        let error = experiment.run_with_error::<AppError>().unwrap_err();
+
+        assert!(matches!(error, AppError::StoreUnavailable));
+    }
+
+    #[test]
+    fn should_convert_other_errors_into_callers_error_type() {
+        let mut experiment: Experiment<'_, String> = Experiment::default();
+        experiment.candidate(|| "candidate".to_string()).unwrap();
+
+        let error = experiment.run_with_error::<AppError>().unwrap_err();
+
+        assert!(matches!(error, AppError::Victors(VictorsErrors::BehaviorMissing(_))));
+    }
+
+    #[test]
+    fn should_include_the_backtrace_when_an_observation_panics() {
+        let mut experiment = Experiment::new("mismatch");
+        experiment.control(|| "control".to_string()).unwrap();
+        experiment.candidate(|| panic!("candidate panicked")).unwrap();
+
+        let error = mismatch_error(experiment);
+        let candidate = error.result.mismatched()[0].clone();
+
+        // backtraces are only captured when enabled via RUST_BACKTRACE
+        if let Some(backtrace) = candidate.backtrace {
+            assert!(error.to_string().contains(backtrace.lines().next().unwrap()));
+        }
+    }
 
     #[test]
     fn should_execute_before_run_when_experiment_is_enabled() {
@@ -462,7 +1190,7 @@ mod tests {
 
         let mut experiment = Experiment::default();
         experiment.add_ignore(|_a, b| {
-            b.value == "ignored"
+            b.value.as_deref() == Some("ignored")
         });
         experiment.control(|| "control".to_string()).unwrap();
         experiment.candidate(|| "candidate".to_string()).unwrap();
@@ -484,6 +1212,83 @@ mod tests {
         assert!(json.is_ok())
     }
 
+    #[test]
+    fn should_return_no_cleaned_value_when_no_clean_block_is_configured() {
+        let experiment: Experiment<'_, i32> = Experiment::default();
+
+        assert_eq!(None, experiment.clean_value(&10));
+    }
+
+    #[test]
+    fn should_call_the_configured_clean_block() {
+        let mut experiment: Experiment<'_, Vec<i32>> = Experiment::default();
+        experiment.clean(|value| value.len());
+
+        assert_eq!(Some(serde_json::json!(3)), experiment.clean_value(&vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn should_publish_cleaned_values_in_place_of_raw_values() {
+        let r: RefCell<Option<ExperimentResult<String>>> = RefCell::new(None);
+
+        let mut experiment = Experiment::default();
+        experiment.control(|| "secret-control".to_string()).unwrap();
+        experiment.candidate(|| "secret-candidate".to_string()).unwrap();
+        experiment.clean(|value: &String| value.len());
+        experiment.result_publisher(InMemoryPublisher::new(|result| {
+            r.replace(Some(result.clone()));
+        }));
+
+        let value = experiment.run().unwrap();
+        assert_eq!("secret-control", value);
+
+        let experiment_result = r.take().unwrap();
+        // raw values are still used for comparison
+        assert!(experiment_result.has_mismatches());
+        assert_eq!(Some(serde_json::json!(14)), experiment_result.control().unwrap().cleaned_value);
+
+        let json = serde_json::to_string(&experiment_result).unwrap();
+        assert!(!json.contains("secret"));
+        assert!(json.contains("\"cleaned_value\":14"));
+    }
+
+    #[test]
+    fn should_round_trip_cleaned_observations_through_bincode() {
+        #[derive(Deserialize)]
+        struct PublishedObservation {
+            experiment_name: String,
+            name: String,
+            value: Option<String>,
+            cleaned_value: Option<u64>,
+            exception: Option<String>,
+            backtrace: Option<String>,
+            state: ObservationState,
+            duration: u128,
+            mismatch_reasons: Vec<String>,
+        }
+
+        let observation = Observation::new(
+            "control".to_string(),
+            "experiment".to_string(),
+            "secret".to_string(),
+            Some(json!(6)),
+            12,
+        );
+
+        let binary = bincode::serialize(&observation).unwrap();
+        let published: PublishedObservation = bincode::deserialize(&binary).unwrap();
+
+        assert_eq!("experiment", published.experiment_name);
+        assert_eq!("control", published.name);
+        assert_eq!(None, published.value);
+        assert_eq!(Some(6), published.cleaned_value);
+        assert_eq!(None, published.exception);
+        assert_eq!(None, published.backtrace);
+        assert_eq!(ObservationState::Completed, published.state);
+        assert_eq!(12, published.duration);
+        assert!(published.mismatch_reasons.is_empty());
+    }
+
     fn create_observation(name: &'static str) -> Observation<u8> {
         return Observation::new(
             name.to_string(),
@@ -1,6 +1,9 @@
 use std::time::Instant;
 use crate::errors::VictorsErrors;
-use serde::{Deserialize, Serialize};
+use serde::{ser::SerializeStruct, Deserialize, Serialize, Serializer};
+use serde_json::Value;
+
+type Comparator<'c, T> = dyn Fn(&T, &T) -> bool + 'c;
 
 // Observation really only needs experiment to get cleaned value.
 // instead of passing in experiment and calling into it to get clean_value
@@ -10,38 +13,140 @@ use serde::{Deserialize, Serialize};
 //   #
 //   # Rescues and reports exceptions in the clean block if they occur.
 
+/// How the behavior of an observation finished.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ObservationState {
+    /// The behavior returned, either with a value or an error
+    Completed,
+    /// The behavior panicked. The panic message is recorded as the observation's exception
+    Panicked,
+    /// The behavior did not finish before its deadline and was abandoned
+    TimedOut,
+}
+
+/// The outcome of comparing the values of two observations, with the reasons they differ.
+/// See [crate::Experiment::explaining_comparator]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub enum Comparison {
+    Equivalent,
+    /// The values differ, for the given human-readable reasons. Empty when no reason was given
+    Different(Vec<String>),
+}
+
+impl Comparison {
+    /// The values differ for a single reason
+    pub fn different<S: Into<String>>(reason: S) -> Self {
+        return Comparison::Different(vec![reason.into()]);
+    }
+
+    pub fn is_equivalent(&self) -> bool {
+        return matches!(self, Comparison::Equivalent);
+    }
+
+    /// Returns the reasons the values differ. Empty when they are equivalent
+    pub fn reasons(&self) -> &[String] {
+        return match self {
+            Comparison::Equivalent => &[],
+            Comparison::Different(reasons) => reasons,
+        };
+    }
+}
+
+impl From<bool> for Comparison {
+    fn from(equivalent: bool) -> Self {
+        return if equivalent {
+            Comparison::Equivalent
+        } else {
+            Comparison::Different(vec![])
+        };
+    }
+}
+
 // TODO: should R also include Copy?
 /// What happened when this named behavior was executed? Immutable.
-#[derive(Clone, Debug, PartialEq, Serialize)]
-pub struct Observation<R: Clone + PartialEq + Serialize> {
+///
+/// When the observation has a cleaned value its raw value is serialized as None.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Observation<R: Serialize> {
     /// The experiment this observation is for
     pub experiment_name: String,
     /// name of the behavior
     pub name: String,
-    pub value: R, // TODO: Does this need to be Option<R>
-    /// cleaned value suitable for publishing. See [Experiment::cleaner] block. None if no cleaner
-    pub cleaned_value: Option<R>, // TODO: what type should this be?
-    // pub exception: Option<VictorsErrors>,
+    /// value returned by the behavior. None if the behavior returned an error
+    pub value: Option<R>,
+    /// cleaned value suitable for publishing. See [crate::Experiment::clean]. None if no cleaner
+    pub cleaned_value: Option<Value>,
+    /// message of the error returned by the behavior, or of the panic it raised, if any
+    pub exception: Option<String>,
+    /// backtrace of the panic when the behavior panicked and backtraces are enabled
+    pub backtrace: Option<String>,
+    pub state: ObservationState,
     pub duration: u128,
-    // exception - should this be a Victor error or would it just be Err? How to attach backtrace?
+    /// why the value differs from the control's, as explained by the experiment's comparator.
+    /// Empty when it does not differ or no reason was given
+    pub mismatch_reasons: Vec<String>,
 }
 
-impl<R: Clone + PartialEq + Serialize> Observation<R> {
+impl<R: Serialize> Observation<R> {
     // TODO: pass in lambda/function block which is executed and duration/value returned
     pub fn new(
         name: String,
         experiment_name: String,
         value: R,
-        cleaned_value: Option<R>,
+        cleaned_value: Option<Value>,
         duration: u128
     ) -> Self {
         return Self {
             name,
-            value,
+            value: Some(value),
             cleaned_value,
-            // exception: None,
+            exception: None,
+            backtrace: None,
+            state: ObservationState::Completed,
+            experiment_name,
+            duration,
+            mismatch_reasons: vec![],
+        };
+    }
+
+    /// Creates an observation for a behavior that returned an error
+    pub fn new_exception(
+        name: String,
+        experiment_name: String,
+        exception: String,
+        duration: u128
+    ) -> Self {
+        return Self {
+            name,
+            value: None,
+            cleaned_value: None,
+            exception: Some(exception),
+            backtrace: None,
+            state: ObservationState::Completed,
+            experiment_name,
+            duration,
+            mismatch_reasons: vec![],
+        };
+    }
+
+    /// Creates an observation for a behavior that panicked
+    pub fn new_panic(
+        name: String,
+        experiment_name: String,
+        message: String,
+        backtrace: Option<String>,
+        duration: u128
+    ) -> Self {
+        return Self {
+            name,
+            value: None,
+            cleaned_value: None,
+            exception: Some(message),
+            backtrace,
+            state: ObservationState::Panicked,
             experiment_name,
             duration,
+            mismatch_reasons: vec![],
         };
     }
 
@@ -63,38 +168,148 @@ impl<R: Clone + PartialEq + Serialize> Observation<R> {
         Self {
             name,
             experiment_name,
-            value,
+            value: Some(value),
             duration: duration.as_millis(),
-            cleaned_value: None
+            cleaned_value: None,
+            exception: None,
+            backtrace: None,
+            state: ObservationState::Completed,
+            mismatch_reasons: vec![],
         }
     }
 
-    pub fn clean_value() {
-        // TODO: Return experiment clean_value option
+
+    /// Creates an observation for a behavior that was abandoned after running past its deadline
+    pub fn new_timed_out(
+        name: String,
+        experiment_name: String,
+        duration: u128
+    ) -> Self {
+        return Self {
+            name,
+            value: None,
+            cleaned_value: None,
+            exception: None,
+            backtrace: None,
+            state: ObservationState::TimedOut,
+            experiment_name,
+            duration,
+            mismatch_reasons: vec![],
+        };
+    }
+
+    /// Did the behavior return an error or panic?
+    pub fn raised(&self) -> bool {
+        return self.exception.is_some();
+    }
+
+    /// Did the behavior panic?
+    pub fn panicked(&self) -> bool {
+        return self.state == ObservationState::Panicked;
+    }
+
+    /// Was the behavior abandoned because it ran past its deadline?
+    pub fn timed_out(&self) -> bool {
+        return self.state == ObservationState::TimedOut;
+    }
+
+    /// Returns a copy of this observation with its value converted by `f`
+    pub(crate) fn map_value<T, F>(&self, f: F) -> Observation<T>
+    where
+        T: Serialize,
+        F: Fn(&R) -> Option<T>,
+    {
+        return Observation {
+            experiment_name: self.experiment_name.clone(),
+            name: self.name.clone(),
+            value: self.value.as_ref().and_then(f),
+            cleaned_value: self.cleaned_value.clone(),
+            exception: self.exception.clone(),
+            backtrace: self.backtrace.clone(),
+            state: self.state,
+            duration: self.duration,
+            mismatch_reasons: self.mismatch_reasons.clone(),
+        };
+    }
+
+    /// Returns the JSON representation of the value this observation is published with: its
+    /// cleaned value if it has one, otherwise its serialized value. None if the behavior did not
+    /// return a value or it can not be serialized.
+    pub(crate) fn json_value(&self) -> Option<Value> {
+        if self.cleaned_value.is_some() {
+            return self.cleaned_value.clone();
+        }
+        return self.value.as_ref().and_then(|value| serde_json::to_value(value).ok());
     }
 
-    // TODO: equivalent_to
     // not sure this needs to be a fn here
     /// Is this observation equivalent to another?
+    ///
+    /// Observations are equivalent when both returned values the comparator considers equivalent or
+    /// both returned errors that compare equal. An observation that returned an error is never equivalent to one
+    /// that returned a value, and an observation that panicked or timed out is never equivalent to
+    /// anything.
     pub fn equivalent_to(
         &self,
         other: &Observation<R>,
-        comparator: Option<fn(a: &R, b: &R) -> bool>,
-        error_comparator: Option<fn(a: &String, b: &String) -> bool>,
+        comparator: &Comparator<'_, R>,
+        error_comparator: Option<&Comparator<'_, String>>,
     ) -> bool {
-        // TODO: check raise // error
-        // if let (Some(exception),  Some(other_exception)) = (&self.exception, &other.exception) {
-        //     return if let Some(error_comparator) = error_comparator {
-        //         error_comparator(exception, other_exception)
-        //     } else {
-        //         exception == other_exception
-        //     }
-        // }
-
-        return if let Some(comparator) = comparator {
-            comparator(&self.value, &other.value)
-        } else {
-            self.value == other.value
+        return self
+            .compare(other, &|a: &R, b: &R| Comparison::from(comparator(a, b)), error_comparator)
+            .is_equivalent();
+    }
+
+    /// Compare this observation to another, explaining why they differ when the comparator gives
+    /// reasons. Equivalence follows the same rules as [Observation::equivalent_to].
+    pub fn compare(
+        &self,
+        other: &Observation<R>,
+        comparator: &dyn Fn(&R, &R) -> Comparison,
+        error_comparator: Option<&Comparator<'_, String>>,
+    ) -> Comparison {
+        if self.state != ObservationState::Completed || other.state != ObservationState::Completed {
+            return Comparison::from(false);
+        }
+
+        match (&self.exception, &other.exception) {
+            (Some(exception), Some(other_exception)) => {
+                return Comparison::from(if let Some(error_comparator) = error_comparator {
+                    error_comparator(exception, other_exception)
+                } else {
+                    exception == other_exception
+                });
+            }
+            (None, None) => {}
+            _ => return Comparison::from(false),
+        }
+
+        return match (&self.value, &other.value) {
+            (Some(value), Some(other_value)) => comparator(value, other_value),
+            (None, None) => Comparison::Equivalent,
+            _ => Comparison::from(false),
         };
     }
 }
+
+impl<R: Serialize> Serialize for Observation<R> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Observation", 9)?;
+        state.serialize_field("experiment_name", &self.experiment_name)?;
+        state.serialize_field("name", &self.name)?;
+        // the raw value never leaves the process once it has been cleaned. The field is still
+        // written so formats without field names, like bincode, can read the struct back
+        if self.cleaned_value.is_some() {
+            state.serialize_field("value", &None::<R>)?;
+        } else {
+            state.serialize_field("value", &self.value)?;
+        }
+        state.serialize_field("cleaned_value", &self.cleaned_value)?;
+        state.serialize_field("exception", &self.exception)?;
+        state.serialize_field("backtrace", &self.backtrace)?;
+        state.serialize_field("state", &self.state)?;
+        state.serialize_field("duration", &self.duration)?;
+        state.serialize_field("mismatch_reasons", &self.mismatch_reasons)?;
+        return state.end();
+    }
+}
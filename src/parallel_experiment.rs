@@ -0,0 +1,685 @@
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    error::Error as StdError,
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use rand::{seq::SliceRandom, thread_rng};
+use serde::Serialize;
+
+use crate::{
+    context::Context,
+    errors::{BehaviorError, BehaviorNotUnique, ErrorAction, Operation, VictorsErrors, VictorsResult},
+    experiment::{Experiment, CONTROL_NAME, DEFAULT_CANDIDATE_NAME},
+    experiment_result::ExperimentResult,
+    observation::{Comparison, Observation},
+    result_publisher::Publisher,
+    sampling::Sampler,
+    unwind::{self, CaughtPanic},
+};
+
+type BoxedControl<'a, R> = Box<dyn Fn() -> Result<R, BehaviorError> + 'a>;
+type ScopedBehavior<'a, R> = Box<dyn Fn() -> Result<R, BehaviorError> + Send + Sync + 'a>;
+type DetachedBehavior<R> = Arc<dyn Fn() -> Result<R, BehaviorError> + Send + Sync>;
+type BehaviorOutcome<R> = Result<Result<R, BehaviorError>, CaughtPanic>;
+
+/// How a candidate is run
+enum Candidate<'a, R> {
+    /// On a scoped thread which is always waited for, so the behavior may borrow from the caller
+    Scoped(ScopedBehavior<'a, R>),
+    /// On a thread of its own which is left behind if the candidate runs past its timeout
+    Detached(DetachedBehavior<R>),
+}
+
+/// An experiment whose candidates run in parallel with the control.
+///
+/// Each candidate runs on its own thread while the control runs on the calling thread, so the
+/// latency of a run is that of its slowest behavior rather than the sum of all of them. Durations
+/// are measured per behavior on the thread that ran it.
+///
+/// The control has no thread bounds. Candidates run on scoped threads, so their behaviors only
+/// need to be `Send + Sync` and may borrow from the caller. The run waits for every one of them.
+/// Candidates that should be abandoned when they run past a timeout are registered with
+/// [ParallelExperiment::detached_candidate] instead and must be `'static`, as their threads may
+/// outlive the run. Values are sent back from the candidate threads, so they must be
+/// `Send + 'static`. Apart from that it has the same result, ignore, comparator and publisher
+/// semantics as [Experiment].
+pub struct ParallelExperiment<'a, R: Serialize + Send + 'static> {
+    experiment: Experiment<'a, R>,
+    control: Option<BoxedControl<'a, R>>,
+    candidates: HashMap<String, Candidate<'a, R>>,
+}
+
+impl<'a, R: PartialEq + Serialize + Send + 'static> Default for ParallelExperiment<'a, R> {
+    /// Creates a new experiment with the name "experiment"
+    fn default() -> Self {
+        return Self {
+            experiment: Experiment::default(),
+            control: None,
+            candidates: Default::default(),
+        };
+    }
+}
+
+impl<'a, R: PartialEq + Serialize + Send + 'static> ParallelExperiment<'a, R> {
+    /// Creates a new experiment whose values are compared with `==`
+    ///
+    /// # Arguments
+    /// * `name` - the name of the experiment
+    pub fn new(name: &'static str) -> Self {
+        return Self {
+            experiment: Experiment::new(name),
+            control: None,
+            candidates: Default::default(),
+        };
+    }
+
+    /// Creates a new experiment with initial context
+    ///
+    /// # Arguments
+    /// * `name` - the name of the experiment
+    /// * `context` - Map of extra experiment data
+    pub fn new_with_context(name: &'static str, context: Context) -> Self {
+        return Self {
+            experiment: Experiment::new_with_context(name, context),
+            control: None,
+            candidates: Default::default(),
+        };
+    }
+}
+
+impl<'a, R: Serialize + Send + 'static> ParallelExperiment<'a, R> {
+    /// Creates a new experiment whose values are compared with the given comparator.
+    /// See [Experiment::with_comparator]
+    pub fn with_comparator<F>(name: &'static str, comparator: F) -> Self
+    where
+        F: Fn(&R, &R) -> bool + 'a,
+    {
+        return Self {
+            experiment: Experiment::with_comparator(name, comparator),
+            control: None,
+            candidates: Default::default(),
+        };
+    }
+
+    /// Returns the name of the experiment
+    pub fn name(&self) -> &String {
+        return &self.experiment.name;
+    }
+
+    /// Returns reference to the experiment context
+    pub fn context(&self) -> &Context {
+        return &self.experiment.context;
+    }
+
+    /// Define a block that determines whether or not the candidate experiments should run.
+    pub fn run_if<F>(&mut self, block: F)
+    where
+        F: Fn() -> bool + 'a,
+    {
+        self.experiment.run_if(block)
+    }
+
+    /// Register a candidate behavior for this experiment, defaults name to "candidate".
+    pub fn candidate<F>(&mut self, f: F) -> VictorsResult<()>
+    where
+        F: Fn() -> R + Send + Sync + 'a,
+    {
+        self.try_candidate_with_name(DEFAULT_CANDIDATE_NAME, move || Ok::<R, Infallible>(f()))
+    }
+
+    /// Register a named candidate behavior for this experiment.
+    pub fn candidate_with_name<F>(&mut self, name: &str, f: F) -> VictorsResult<()>
+    where
+        F: Fn() -> R + Send + Sync + 'a,
+    {
+        self.try_candidate_with_name(name, move || Ok::<R, Infallible>(f()))
+    }
+
+    /// Register the control behavior for this experiment.
+    pub fn control<F>(&mut self, f: F) -> VictorsResult<()>
+    where
+        F: Fn() -> R + 'a,
+    {
+        self.try_control(move || Ok::<R, Infallible>(f()))
+    }
+
+    /// Register a fallible candidate behavior for this experiment, defaults name to "candidate".
+    /// See [Experiment::try_candidate]
+    pub fn try_candidate<F, E>(&mut self, f: F) -> VictorsResult<()>
+    where
+        F: Fn() -> Result<R, E> + Send + Sync + 'a,
+        E: Into<BehaviorError>,
+    {
+        self.try_candidate_with_name(DEFAULT_CANDIDATE_NAME, f)
+    }
+
+    /// Register a named fallible candidate behavior for this experiment.
+    pub fn try_candidate_with_name<F, E>(&mut self, name: &str, f: F) -> VictorsResult<()>
+    where
+        F: Fn() -> Result<R, E> + Send + Sync + 'a,
+        E: Into<BehaviorError>,
+    {
+        self.add_candidate(name, Candidate::Scoped(Box::new(move || f().map_err(Into::into))))
+    }
+
+    /// Register a named candidate behavior which is abandoned when it runs past its timeout.
+    ///
+    /// Unlike other candidates it runs on a thread that isn't waited for, so the behavior must be
+    /// `'static`. See [ParallelExperiment::timeout]
+    pub fn detached_candidate<F>(&mut self, name: &str, f: F) -> VictorsResult<()>
+    where
+        F: Fn() -> R + Send + Sync + 'static,
+    {
+        self.try_detached_candidate(name, move || Ok::<R, Infallible>(f()))
+    }
+
+    /// Register a named fallible candidate behavior which is abandoned when it runs past its
+    /// timeout. See [ParallelExperiment::detached_candidate]
+    pub fn try_detached_candidate<F, E>(&mut self, name: &str, f: F) -> VictorsResult<()>
+    where
+        F: Fn() -> Result<R, E> + Send + Sync + 'static,
+        E: Into<BehaviorError>,
+    {
+        self.add_candidate(name, Candidate::Detached(Arc::new(move || f().map_err(Into::into))))
+    }
+
+    /// Register a fallible control behavior for this experiment.
+    /// See [Experiment::try_control]
+    pub fn try_control<F, E>(&mut self, f: F) -> VictorsResult<()>
+    where
+        F: Fn() -> Result<R, E> + 'a,
+        E: Into<BehaviorError>,
+    {
+        if self.control.is_some() || self.candidates.contains_key(CONTROL_NAME) {
+            return Err(self.behavior_not_unique(CONTROL_NAME));
+        }
+        self.control = Some(Box::new(move || f().map_err(Into::into)));
+
+        return Ok(());
+    }
+
+    fn add_candidate(&mut self, name: &str, candidate: Candidate<'a, R>) -> VictorsResult<()> {
+        if self.candidates.contains_key(name) || (name == CONTROL_NAME && self.control.is_some()) {
+            return Err(self.behavior_not_unique(name));
+        }
+        self.candidates.insert(name.to_string(), candidate);
+
+        return Ok(());
+    }
+
+    fn behavior_not_unique(&self, name: &str) -> VictorsErrors {
+        return VictorsErrors::BehaviorNotUnique(BehaviorNotUnique {
+            experiment_name: self.experiment.name.to_string(),
+            name: name.to_string(),
+        });
+    }
+
+    /// Define a block of code to run before an experiment begins, if the experiment is enabled.
+    pub fn before_run<F>(&mut self, f: F)
+    where
+        F: Fn() + 'a,
+    {
+        self.experiment.before_run(f)
+    }
+
+    /// A block to clean an observed value for publishing or storing.
+    /// See [Experiment::clean]
+    pub fn clean<F, C>(&mut self, f: F)
+    where
+        F: Fn(&R) -> C + 'a,
+        C: Serialize,
+    {
+        self.experiment.clean(f)
+    }
+
+    pub fn add_context(&mut self, context: Context) {
+        self.experiment.add_context(context)
+    }
+
+    /// Configure experiment to ignore observations based on the given block.
+    /// See [Experiment::add_ignore]
+    pub fn add_ignore<F>(&mut self, ignore_block: F)
+    where
+        F: Fn(&Observation<R>, &Observation<R>) -> bool + 'a,
+    {
+        self.experiment.add_ignore(ignore_block)
+    }
+
+    /// See [Experiment::ignore_mismatch_observation]
+    pub fn ignore_mismatch_observation(&self, control: &Observation<R>, candidate: &Observation<R>) -> bool {
+        self.experiment.ignore_mismatch_observation(control, candidate)
+    }
+
+    pub fn observations_are_equivalent(&self, a: &Observation<R>, b: &Observation<R>) -> bool {
+        return self.experiment.observations_are_equivalent(a, b);
+    }
+
+    pub fn enabled<F>(&mut self, enabled: F)
+    where
+        F: Fn() -> bool + 'a,
+    {
+        self.experiment.enabled(enabled)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        return self.experiment.is_enabled();
+    }
+
+    /// See [Experiment::sampler]
+    pub fn sampler<S: Sampler + 'a>(&mut self, sampler: S) {
+        self.experiment.sampler(sampler)
+    }
+
+    /// See [Experiment::percent_enabled]
+    pub fn percent_enabled(&mut self, percent: f64) {
+        self.experiment.percent_enabled(percent)
+    }
+
+    pub fn result_publisher<T: Publisher<R> + 'a>(&mut self, publisher: T) {
+        self.experiment.result_publisher(publisher)
+    }
+
+    /// Decide what happens when an operation of the experiment fails. See [Experiment::on_error]
+    pub fn on_error<F>(&mut self, handler: F)
+    where
+        F: Fn(Operation, &VictorsErrors) -> ErrorAction + 'a,
+    {
+        self.experiment.on_error(handler)
+    }
+
+    /// See [Experiment::comparator]
+    pub fn comparator<F>(&mut self, comparator: F)
+    where
+        F: Fn(&R, &R) -> bool + 'a,
+    {
+        self.experiment.comparator(comparator)
+    }
+
+    /// See [Experiment::explaining_comparator]
+    pub fn explaining_comparator<F>(&mut self, comparator: F)
+    where
+        F: Fn(&R, &R) -> Comparison + 'a,
+    {
+        self.experiment.explaining_comparator(comparator)
+    }
+
+    /// See [Experiment::error_comparator]
+    pub fn error_comparator<F>(&mut self, comparator: F)
+    where
+        F: Fn(&String, &String) -> bool + 'a,
+    {
+        self.experiment.error_comparator(comparator)
+    }
+
+    /// Whether to return an error when the control and candidate mismatch.
+    /// See [Experiment::err_on_mismatches]
+    pub fn err_on_mismatches(&mut self, err_on_mismatches: bool) {
+        self.experiment.err_on_mismatches(err_on_mismatches)
+    }
+
+    /// A block which builds the error returned when the observations mismatch.
+    /// See [Experiment::raise_with]
+    pub fn raise_with<F, E>(&mut self, block: F)
+    where
+        F: Fn(&ExperimentResult<R>) -> E + 'a,
+        E: StdError + Send + Sync + 'static,
+    {
+        self.experiment.raise_with(block)
+    }
+
+    /// Abandon any detached candidate that runs longer than `timeout`, unless it has a timeout of
+    /// its own.
+    ///
+    /// Abandoned candidates are recorded as timed out observations. Their threads are left to
+    /// finish in the background and whatever they return is discarded. Candidates which aren't
+    /// detached are always waited for, see [ParallelExperiment::detached_candidate].
+    pub fn timeout(&mut self, timeout: Duration) {
+        self.experiment.set_timeout(timeout)
+    }
+
+    /// Abandon the named detached candidate if it runs longer than `timeout`.
+    /// See [ParallelExperiment::timeout]
+    pub fn candidate_timeout(&mut self, name: &str, timeout: Duration) {
+        self.experiment.set_candidate_timeout(name, timeout)
+    }
+
+    /// Same as [ParallelExperiment::run] but returns the caller's own error type.
+    /// See [Experiment::run_with_error]
+    pub fn run_with_error<E>(&mut self) -> Result<R, E>
+    where
+        E: From<VictorsErrors> + StdError + 'static,
+    {
+        return self.run().map_err(VictorsErrors::into_error);
+    }
+
+    /// Run all the behaviors for this experiment, observing each and publishing the results.
+    /// Return the result of the control once every candidate has finished or been abandoned.
+    pub fn run(&mut self) -> VictorsResult<R> {
+        let control = match &self.control {
+            None => return Err(self.experiment.behavior_missing(CONTROL_NAME)),
+            Some(control) => control,
+        };
+
+        if !self.experiment.start_run(self.candidates.len() + 1)? {
+            return control().map_err(|err| self.experiment.behavior_failed(CONTROL_NAME, err));
+        }
+
+        let mut keys = Vec::from_iter(self.candidates.keys().cloned());
+        keys.shuffle(&mut thread_rng());
+
+        let experiment = &self.experiment;
+        let candidates = &self.candidates;
+        // crossbeam's scope rather than std's, as the latter can't borrow from the caller on the
+        // pinned toolchain
+        let scoped = crossbeam_utils::thread::scope(|scope| {
+            let mut observations = vec![];
+            let mut pending = HashMap::new();
+            let (sender, receiver) = mpsc::channel();
+            let started = Instant::now();
+            for key in keys {
+                let sender = sender.clone();
+                let name = key.clone();
+                let thread_name = format!("victors-{}-{}", experiment.name, key);
+                let (spawned, deadline) = match &candidates[&key] {
+                    Candidate::Scoped(behavior) => {
+                        let spawned = scope.builder().name(thread_name).spawn(move |_| {
+                            let start = Instant::now();
+                            let outcome = unwind::catch(behavior);
+                            let _ = sender.send((name, outcome, start.elapsed()));
+                        });
+                        (spawned.map(|_| ()), None)
+                    }
+                    Candidate::Detached(behavior) => {
+                        let behavior = Arc::clone(behavior);
+                        let spawned = thread::Builder::new().name(thread_name).spawn(move || {
+                            let start = Instant::now();
+                            let outcome = unwind::catch(|| behavior());
+                            // the receiver is gone when the candidate has been abandoned
+                            let _ = sender.send((name, outcome, start.elapsed()));
+                        });
+                        let deadline = experiment.candidate_timeout(&key).map(|timeout| started + timeout);
+                        (spawned.map(|_| ()), deadline)
+                    }
+                };
+                match spawned {
+                    Ok(()) => {
+                        pending.insert(key, deadline);
+                    }
+                    Err(err) => {
+                        let (observation, _) = experiment.observe(&key, Ok(Err(err.into())), Duration::default());
+                        observations.push(observation);
+                    }
+                }
+            }
+            drop(sender);
+
+            // the control runs on the calling thread so that its panics propagate as usual
+            let start = Instant::now();
+            let control_outcome: BehaviorOutcome<R> = Ok(control());
+            let (observation, control_error) = experiment.observe(CONTROL_NAME, control_outcome, start.elapsed());
+            observations.push(observation);
+            let control_index = observations.len() - 1;
+
+            while !pending.is_empty() {
+                let next_deadline = pending.values().flatten().min().copied();
+                let received = match next_deadline {
+                    None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                    Some(deadline) => receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+                };
+
+                match received {
+                    Ok((key, outcome, duration)) => {
+                        if pending.remove(&key).is_some() {
+                            let (observation, _) = experiment.observe(&key, outcome, duration);
+                            observations.push(observation);
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => {
+                        let now = Instant::now();
+                        let expired: Vec<String> = pending
+                            .iter()
+                            .filter(|(_, deadline)| matches!(deadline, Some(deadline) if *deadline <= now))
+                            .map(|(key, _)| key.to_string())
+                            .collect();
+                        for key in expired {
+                            pending.remove(&key);
+                            observations.push(Observation::new_timed_out(
+                                key,
+                                experiment.name.to_string(),
+                                now.duration_since(started).as_millis(),
+                            ));
+                        }
+                    }
+                    // every remaining candidate thread died without reporting back
+                    Err(RecvTimeoutError::Disconnected) => {
+                        for (key, _) in pending.drain() {
+                            let outcome = Err(CaughtPanic {
+                                message: "candidate thread panicked".to_string(),
+                                backtrace: None,
+                            });
+                            let (observation, _) = experiment.observe(&key, outcome, Duration::default());
+                            observations.push(observation);
+                        }
+                    }
+                }
+            }
+            return (observations, control_index, control_error);
+        });
+        // candidates catch their own panics and a panicking control is resumed by the scope
+        let (observations, control_index, control_error) = scoped.expect("candidate threads don't panic");
+
+        let result = self.experiment.build_result(CONTROL_NAME, observations, Some(control_index))?;
+        return self.experiment.publish_result(CONTROL_NAME, result, control_error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        rc::Rc,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Barrier,
+        },
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        errors::VictorsErrors, observation::ObservationState, result_publisher::InMemoryPublisher, ExperimentResult,
+        ParallelExperiment,
+    };
+
+    #[test]
+    fn should_return_control_result() {
+        let mut experiment = ParallelExperiment::default();
+        experiment.control(|| "control").unwrap();
+        experiment.candidate(|| "candidate").unwrap();
+
+        let result = experiment.run().unwrap();
+
+        assert_eq!("control", result);
+    }
+
+    #[test]
+    fn should_run_control_and_candidates_concurrently() {
+        // every behavior waits on the barrier so the run can only finish if they run at the same time
+        let barrier = Arc::new(Barrier::new(3));
+
+        let mut experiment = ParallelExperiment::default();
+        let control_barrier = Arc::clone(&barrier);
+        experiment.control(move || { control_barrier.wait(); 1 }).unwrap();
+        let candidate_barrier = Arc::clone(&barrier);
+        experiment.candidate(move || { candidate_barrier.wait(); 1 }).unwrap();
+        let second_barrier = Arc::clone(&barrier);
+        experiment.candidate_with_name("second", move || { second_barrier.wait(); 1 }).unwrap();
+
+        assert_eq!(1, experiment.run().unwrap());
+    }
+
+    #[test]
+    fn should_measure_duration_of_each_behavior() {
+        let r: RefCell<Option<ExperimentResult<u8>>> = RefCell::new(None);
+
+        let mut experiment = ParallelExperiment::default();
+        experiment.control(|| 1).unwrap();
+        experiment.candidate(|| {
+            thread::sleep(Duration::from_millis(50));
+            1
+        }).unwrap();
+        experiment.result_publisher(InMemoryPublisher::new(|result| {
+            r.replace(Some(result.clone()));
+        }));
+
+        experiment.run().unwrap();
+
+        let result = r.take().unwrap();
+        let candidate = result.observations().iter().find(|o| o.name == "candidate").unwrap();
+        assert!(result.matched());
+        assert!(result.control().unwrap().duration < 50);
+        assert!(candidate.duration >= 50);
+    }
+
+    #[test]
+    fn should_wait_for_candidates_before_returning() {
+        let finished = Arc::new(AtomicBool::new(false));
+
+        let mut experiment = ParallelExperiment::default();
+        experiment.control(|| 1).unwrap();
+        let candidate_finished = Arc::clone(&finished);
+        experiment.candidate(move || {
+            thread::sleep(Duration::from_millis(20));
+            candidate_finished.store(true, Ordering::SeqCst);
+            1
+        }).unwrap();
+
+        experiment.run().unwrap();
+
+        assert!(finished.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn should_swallow_candidate_errors_and_panics() {
+        let r: RefCell<Option<ExperimentResult<u8>>> = RefCell::new(None);
+
+        let mut experiment = ParallelExperiment::default();
+        experiment.control(|| 1).unwrap();
+        experiment.try_candidate(|| Err("candidate failed")).unwrap();
+        experiment.candidate_with_name("panics", || panic!("candidate exploded")).unwrap();
+        experiment.result_publisher(InMemoryPublisher::new(|result| {
+            r.replace(Some(result.clone()));
+        }));
+
+        assert_eq!(1, experiment.run().unwrap());
+
+        let result = r.take().unwrap();
+        assert_eq!(2, result.mismatched().len());
+        assert!(result.mismatched().iter().any(|observation| observation.panicked()));
+    }
+
+    #[test]
+    fn should_abandon_candidates_that_run_past_the_experiment_timeout() {
+        let r: RefCell<Option<ExperimentResult<u8>>> = RefCell::new(None);
+
+        let mut experiment = ParallelExperiment::default();
+        experiment.control(|| 1).unwrap();
+        experiment.detached_candidate("candidate", || {
+            thread::sleep(Duration::from_secs(5));
+            1
+        }).unwrap();
+        experiment.timeout(Duration::from_millis(20));
+        experiment.result_publisher(InMemoryPublisher::new(|result| {
+            r.replace(Some(result.clone()));
+        }));
+
+        let start = Instant::now();
+        assert_eq!(1, experiment.run().unwrap());
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        let result = r.take().unwrap();
+        let candidate = result.mismatched()[0];
+        assert_eq!("candidate", candidate.name);
+        assert_eq!(ObservationState::TimedOut, candidate.state);
+        assert!(candidate.timed_out());
+    }
+
+    #[test]
+    fn should_prefer_candidate_timeout_over_experiment_timeout() {
+        let r: RefCell<Option<ExperimentResult<u8>>> = RefCell::new(None);
+
+        let mut experiment = ParallelExperiment::default();
+        experiment.control(|| 1).unwrap();
+        experiment.detached_candidate("candidate", || {
+            thread::sleep(Duration::from_millis(50));
+            1
+        }).unwrap();
+        experiment.detached_candidate("slow", || {
+            thread::sleep(Duration::from_secs(5));
+            1
+        }).unwrap();
+        experiment.timeout(Duration::from_millis(20));
+        experiment.candidate_timeout("candidate", Duration::from_secs(5));
+        experiment.result_publisher(InMemoryPublisher::new(|result| {
+            r.replace(Some(result.clone()));
+        }));
+
+        experiment.run().unwrap();
+
+        let result = r.take().unwrap();
+        assert_eq!(1, result.mismatched().len());
+        assert_eq!("slow", result.mismatched()[0].name);
+    }
+
+    #[test]
+    fn should_wait_for_candidates_which_are_not_detached_despite_timeout() {
+        let r: RefCell<Option<ExperimentResult<u8>>> = RefCell::new(None);
+
+        let mut experiment = ParallelExperiment::default();
+        experiment.control(|| 1).unwrap();
+        experiment.candidate(|| {
+            thread::sleep(Duration::from_millis(50));
+            1
+        }).unwrap();
+        experiment.timeout(Duration::from_millis(10));
+        experiment.result_publisher(InMemoryPublisher::new(|result| {
+            r.replace(Some(result.clone()));
+        }));
+
+        experiment.run().unwrap();
+
+        assert!(r.take().unwrap().matched());
+    }
+
+    #[test]
+    fn should_let_behaviors_borrow_from_the_caller() {
+        let values = vec![1, 2, 3];
+        // not Send, which is fine for the control as it runs on the calling thread
+        let control_values = Rc::new(values.clone());
+
+        let mut experiment = ParallelExperiment::default();
+        experiment.control(|| control_values.iter().sum::<i32>()).unwrap();
+        experiment.candidate(|| values.iter().sum()).unwrap();
+        experiment.candidate_with_name("reversed", || values.iter().rev().sum()).unwrap();
+
+        assert_eq!(6, experiment.run().unwrap());
+    }
+
+    #[test]
+    fn should_return_error_returned_by_control() {
+        let mut experiment: ParallelExperiment<'_, u8> = ParallelExperiment::default();
+        experiment.try_control(|| Err("control failed")).unwrap();
+        experiment.candidate(|| 1).unwrap();
+
+        let result = experiment.run();
+
+        assert!(matches!(result, Err(VictorsErrors::BehaviorFailed(_))));
+    }
+}
@@ -0,0 +1,420 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use crate::{
+    errors::PublishError,
+    experiment_result::ExperimentResult,
+    result_publisher::{Publisher, ScientistValue},
+    unwind,
+};
+
+const DEFAULT_CAPACITY: usize = 1_024;
+const DEFAULT_BATCH_SIZE: usize = 64;
+
+/// What a [BufferedPublisher] does with a result when its buffer is full.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Overflow {
+    /// Drop the result being published
+    DropNewest,
+    /// Drop the oldest buffered result to make room for the one being published
+    DropOldest,
+    /// Block the experiment until there is room in the buffer
+    Block,
+}
+
+struct Queue {
+    results: VecDeque<ExperimentResult<ScientistValue>>,
+    /// number of results taken off the queue which the background thread is still publishing
+    in_flight: usize,
+    closed: bool,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    /// signalled when results are added or the publisher is closed
+    not_empty: Condvar,
+    /// signalled when results are taken off the queue or finish publishing
+    drained: Condvar,
+    capacity: usize,
+    batch_size: usize,
+    linger: Duration,
+    overflow: Overflow,
+    dropped: AtomicU64,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        return self.queue.lock().expect("buffered publisher queue poisoned");
+    }
+}
+
+/// Builds a [BufferedPublisher]. See [BufferedPublisher::builder]
+pub struct BufferedPublisherBuilder<P> {
+    publisher: P,
+    capacity: usize,
+    batch_size: usize,
+    linger: Duration,
+    overflow: Overflow,
+}
+
+impl<P: Publisher<ScientistValue> + Send + 'static> BufferedPublisherBuilder<P> {
+    /// Maximum number of results waiting to be published. Defaults to 1,024
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        return self;
+    }
+
+    /// Maximum number of results handed to the wrapped publisher at once. Defaults to 64
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        return self;
+    }
+
+    /// How long to wait for a batch to fill up before publishing a partial one. Defaults to not
+    /// waiting at all
+    pub fn linger(mut self, linger: Duration) -> Self {
+        self.linger = linger;
+        return self;
+    }
+
+    /// What to do with results published while the buffer is full. Defaults to
+    /// [Overflow::DropNewest]
+    pub fn overflow(mut self, overflow: Overflow) -> Self {
+        self.overflow = overflow;
+        return self;
+    }
+
+    /// Start the background thread and return the publisher
+    pub fn build(self) -> BufferedPublisher {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue {
+                results: VecDeque::with_capacity(self.capacity),
+                in_flight: 0,
+                closed: false,
+            }),
+            not_empty: Condvar::new(),
+            drained: Condvar::new(),
+            capacity: self.capacity,
+            batch_size: self.batch_size,
+            linger: self.linger,
+            overflow: self.overflow,
+            dropped: AtomicU64::new(0),
+        });
+
+        let worker_shared = Arc::clone(&shared);
+        let publisher = self.publisher;
+        let worker = thread::Builder::new()
+            .name("victors-buffered-publisher".to_string())
+            .spawn(move || drain(&worker_shared, &publisher))
+            .expect("failed to spawn buffered publisher thread");
+
+        return BufferedPublisher {
+            shared,
+            worker: Some(worker),
+        };
+    }
+}
+
+/// Publishes results from a background thread so that slow publishers don't hold up experiments.
+///
+/// Results are erased, see [ExperimentResult::erase], and put in a bounded buffer which a
+/// background thread drains in batches into the wrapped publisher. Dropping the publisher waits
+/// for every buffered result to be published.
+///
+/// Errors returned by the wrapped publisher can't reach the experiments that published the results
+/// so they are logged instead.
+pub struct BufferedPublisher {
+    shared: Arc<Shared>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl BufferedPublisher {
+    /// Wraps `publisher` with the default settings
+    ///
+    /// # Arguments
+    /// * `publisher` - the publisher results are handed to from the background thread
+    pub fn new<P: Publisher<ScientistValue> + Send + 'static>(publisher: P) -> Self {
+        return BufferedPublisher::builder(publisher).build();
+    }
+
+    /// Returns a builder to configure how `publisher` is wrapped
+    ///
+    /// # Arguments
+    /// * `publisher` - the publisher results are handed to from the background thread
+    pub fn builder<P: Publisher<ScientistValue> + Send + 'static>(publisher: P) -> BufferedPublisherBuilder<P> {
+        return BufferedPublisherBuilder {
+            publisher,
+            capacity: DEFAULT_CAPACITY,
+            batch_size: DEFAULT_BATCH_SIZE,
+            linger: Duration::ZERO,
+            overflow: Overflow::DropNewest,
+        };
+    }
+
+    /// Returns the number of results dropped because the buffer was full
+    pub fn dropped(&self) -> u64 {
+        return self.shared.dropped.load(Ordering::Relaxed);
+    }
+
+    /// Returns the number of results waiting to be published
+    pub fn pending(&self) -> usize {
+        return self.shared.lock().results.len();
+    }
+
+    /// Block until every result published so far has been handed to the wrapped publisher
+    pub fn flush(&self) {
+        let mut queue = self.shared.lock();
+        while !queue.results.is_empty() || queue.in_flight > 0 {
+            queue = self.shared.drained.wait(queue).expect("buffered publisher queue poisoned");
+        }
+    }
+
+    fn enqueue(&self, result: ExperimentResult<ScientistValue>) {
+        let shared = &self.shared;
+        let mut queue = shared.lock();
+        if queue.results.len() >= shared.capacity {
+            match shared.overflow {
+                Overflow::DropNewest => {
+                    shared.dropped.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+                Overflow::DropOldest => {
+                    queue.results.pop_front();
+                    shared.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                Overflow::Block => {
+                    while queue.results.len() >= shared.capacity && !queue.closed {
+                        queue = shared.drained.wait(queue).expect("buffered publisher queue poisoned");
+                    }
+                }
+            }
+        }
+        queue.results.push_back(result);
+        shared.not_empty.notify_one();
+    }
+}
+
+impl<R: Serialize> Publisher<R> for BufferedPublisher {
+    fn publish(&self, result: &ExperimentResult<R>) -> Result<(), PublishError> {
+        self.enqueue(result.erase());
+        return Ok(());
+    }
+}
+
+impl Drop for BufferedPublisher {
+    fn drop(&mut self) {
+        self.shared.lock().closed = true;
+        self.shared.not_empty.notify_all();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Background loop handing batches of results to the wrapped publisher until the buffered
+/// publisher is dropped and the buffer is empty.
+fn drain<P: Publisher<ScientistValue>>(shared: &Shared, publisher: &P) {
+    loop {
+        let mut queue = shared.lock();
+        while queue.results.is_empty() && !queue.closed {
+            queue = shared.not_empty.wait(queue).expect("buffered publisher queue poisoned");
+        }
+        if queue.results.is_empty() {
+            return;
+        }
+
+        let deadline = Instant::now() + shared.linger;
+        while queue.results.len() < shared.batch_size && !queue.closed {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            queue = shared
+                .not_empty
+                .wait_timeout(queue, deadline - now)
+                .expect("buffered publisher queue poisoned")
+                .0;
+        }
+
+        let size = queue.results.len().min(shared.batch_size);
+        let batch: Vec<ExperimentResult<ScientistValue>> = queue.results.drain(..size).collect();
+        queue.in_flight = batch.len();
+        drop(queue);
+        shared.drained.notify_all();
+
+        // a panicking publisher must not take the thread down, experiments would block forever
+        match unwind::catch(|| publisher.publish_batch(&batch)) {
+            Ok(Ok(())) => {}
+            Ok(Err(error)) => log::error!("failed to publish {} experiment results: {}", batch.len(), error),
+            Err(panic) => log::error!("publisher panicked publishing {} experiment results: {}", batch.len(), panic.message),
+        }
+
+        shared.lock().in_flight = 0;
+        shared.drained.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Condvar, Mutex,
+        },
+        thread,
+        time::Duration,
+    };
+
+    use crate::{
+        errors::PublishError,
+        publishers::buffered::{BufferedPublisher, Overflow},
+        result_publisher::ScientistValue,
+        Experiment, ExperimentResult, Publisher,
+    };
+
+    /// Records the control value of each result, per batch, once its gate is opened
+    #[derive(Clone, Default)]
+    struct GatedPublisher {
+        open: Arc<(Mutex<bool>, Condvar)>,
+        batches: Arc<Mutex<Vec<Vec<i64>>>>,
+    }
+
+    impl GatedPublisher {
+        fn open(&self) {
+            *self.open.0.lock().unwrap() = true;
+            self.open.1.notify_all();
+        }
+
+        fn batches(&self) -> Vec<Vec<i64>> {
+            return self.batches.lock().unwrap().clone();
+        }
+    }
+
+    impl Publisher<ScientistValue> for GatedPublisher {
+        fn publish(&self, result: &ExperimentResult<ScientistValue>) -> Result<(), PublishError> {
+            return self.publish_batch(std::slice::from_ref(result));
+        }
+
+        fn publish_batch(&self, results: &[ExperimentResult<ScientistValue>]) -> Result<(), PublishError> {
+            let mut open = self.open.0.lock().unwrap();
+            while !*open {
+                open = self.open.1.wait(open).unwrap();
+            }
+            let values = results
+                .iter()
+                .map(|result| result.control().unwrap().value.as_ref().unwrap().as_i64().unwrap())
+                .collect();
+            self.batches.lock().unwrap().push(values);
+            return Ok(());
+        }
+    }
+
+    fn publish(publisher: &BufferedPublisher, value: i64) {
+        let mut experiment = Experiment::new("buffered");
+        experiment.control(move || value).unwrap();
+        experiment.candidate(move || value).unwrap();
+        experiment.result_publisher(publisher);
+        experiment.run().unwrap();
+    }
+
+    // publish a first result and wait for the background thread to get stuck publishing it
+    fn block_worker(publisher: &BufferedPublisher) {
+        publish(publisher, 1);
+        while publisher.pending() > 0 {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn should_publish_buffered_results_in_batches() {
+        let inner = GatedPublisher::default();
+        let publisher = BufferedPublisher::builder(inner.clone()).batch_size(2).build();
+
+        block_worker(&publisher);
+        for value in 2..=6 {
+            publish(&publisher, value);
+        }
+        inner.open();
+        publisher.flush();
+
+        assert_eq!(vec![vec![1], vec![2, 3], vec![4, 5], vec![6]], inner.batches());
+        assert_eq!(0, publisher.dropped());
+    }
+
+    #[test]
+    fn should_drop_newest_results_when_full() {
+        let inner = GatedPublisher::default();
+        let publisher = BufferedPublisher::builder(inner.clone())
+            .capacity(2)
+            .overflow(Overflow::DropNewest)
+            .build();
+
+        block_worker(&publisher);
+        for value in 2..=5 {
+            publish(&publisher, value);
+        }
+        assert_eq!(2, publisher.dropped());
+        inner.open();
+        drop(publisher);
+
+        assert_eq!(vec![vec![1], vec![2, 3]], inner.batches());
+    }
+
+    #[test]
+    fn should_drop_oldest_results_when_full() {
+        let inner = GatedPublisher::default();
+        let publisher = BufferedPublisher::builder(inner.clone())
+            .capacity(2)
+            .overflow(Overflow::DropOldest)
+            .build();
+
+        block_worker(&publisher);
+        for value in 2..=5 {
+            publish(&publisher, value);
+        }
+        assert_eq!(2, publisher.dropped());
+        inner.open();
+        drop(publisher);
+
+        assert_eq!(vec![vec![1], vec![4, 5]], inner.batches());
+    }
+
+    #[test]
+    fn should_block_when_full() {
+        let inner = GatedPublisher::default();
+        let publisher = Arc::new(
+            BufferedPublisher::builder(inner.clone())
+                .capacity(1)
+                .overflow(Overflow::Block)
+                .build(),
+        );
+
+        block_worker(&publisher);
+        publish(&publisher, 2);
+        let blocked_publisher = Arc::clone(&publisher);
+        let published = Arc::new(AtomicBool::new(false));
+        let blocked_published = Arc::clone(&published);
+        let blocked = thread::spawn(move || {
+            publish(&blocked_publisher, 3);
+            blocked_published.store(true, Ordering::SeqCst);
+        });
+        thread::sleep(Duration::from_millis(50));
+        assert!(!published.load(Ordering::SeqCst));
+
+        inner.open();
+        blocked.join().unwrap();
+        publisher.flush();
+
+        assert_eq!(vec![1, 2, 3], inner.batches().concat());
+        assert_eq!(0, publisher.dropped());
+    }
+}
@@ -0,0 +1,373 @@
+use std::marker::PhantomData;
+
+use rand::{thread_rng, Rng};
+use serde::Serialize;
+
+use crate::{errors::PublishError, experiment_result::ExperimentResult, result_publisher::Publisher, sampling::clamp_percent};
+
+/// Publishes every result to each of several publishers, in the order they were added.
+///
+/// A publisher failing doesn't stop the remaining ones from being published to. The first error
+/// is returned.
+pub struct FanOut<'a, R: Serialize> {
+    publishers: Vec<Box<dyn Publisher<R> + 'a>>,
+}
+
+impl<'a, R: Serialize> FanOut<'a, R> {
+    /// Creates a fan out without any publishers
+    pub fn new() -> Self {
+        return Self { publishers: vec![] };
+    }
+
+    /// Add a publisher to publish results to
+    pub fn with<P: Publisher<R> + 'a>(mut self, publisher: P) -> Self {
+        self.publishers.push(Box::new(publisher));
+        return self;
+    }
+}
+
+impl<'a, R: Serialize> Default for FanOut<'a, R> {
+    fn default() -> Self {
+        return FanOut::new();
+    }
+}
+
+impl<'a, R: Serialize> Publisher<R> for FanOut<'a, R> {
+    fn publish(&self, result: &ExperimentResult<R>) -> Result<(), PublishError> {
+        return first_error(self.publishers.iter().map(|publisher| publisher.publish(result)));
+    }
+
+    fn publish_batch(&self, results: &[ExperimentResult<R>]) -> Result<(), PublishError> {
+        return first_error(self.publishers.iter().map(|publisher| publisher.publish_batch(results)));
+    }
+}
+
+/// Only publishes results the predicate returns true for.
+///
+/// ```
+/// use victors::{publishers::Filter, result_publisher::NoopPublisher, ExperimentResult};
+///
+/// let publisher = Filter::new(NoopPublisher, |result: &ExperimentResult<i32>| result.has_mismatches());
+/// ```
+pub struct Filter<P, F> {
+    publisher: P,
+    predicate: F,
+}
+
+impl<P, F> Filter<P, F> {
+    /// # Arguments
+    /// * `publisher` - the publisher to publish matching results to
+    /// * `predicate` - returns whether a result should be published
+    pub fn new(publisher: P, predicate: F) -> Self {
+        return Self { publisher, predicate };
+    }
+}
+
+impl<R, P, F> Publisher<R> for Filter<P, F>
+where
+    R: Serialize,
+    P: Publisher<R>,
+    F: Fn(&ExperimentResult<R>) -> bool,
+{
+    fn publish(&self, result: &ExperimentResult<R>) -> Result<(), PublishError> {
+        if (self.predicate)(result) {
+            return self.publisher.publish(result);
+        }
+        return Ok(());
+    }
+
+    fn publish_batch(&self, results: &[ExperimentResult<R>]) -> Result<(), PublishError> {
+        return publish_kept(&self.publisher, results, |result| (self.predicate)(result));
+    }
+}
+
+/// Publishes every result that didn't match but only a random percentage of matching results.
+///
+/// Useful to keep the volume of published results down once an experiment mostly matches.
+pub struct Sampled<P> {
+    publisher: P,
+    percent: f64,
+}
+
+impl<P> Sampled<P> {
+    /// # Arguments
+    /// * `publisher` - the publisher to publish kept results to
+    /// * `percent` - percentage of matching results to keep, between 0 and 100, values outside of
+    ///   that range are clamped and NaN is 0
+    pub fn new(publisher: P, percent: f64) -> Self {
+        return Self {
+            publisher,
+            percent: clamp_percent(percent),
+        };
+    }
+
+    /// Returns the percentage of matching results that are published
+    pub fn percent(&self) -> f64 {
+        return self.percent;
+    }
+
+    fn keep<R: Serialize>(&self, result: &ExperimentResult<R>) -> bool {
+        if !result.matched() {
+            return true;
+        }
+        return self.percent >= 100.0 || (self.percent > 0.0 && thread_rng().gen_range(0.0..100.0) < self.percent);
+    }
+}
+
+impl<R: Serialize, P: Publisher<R>> Publisher<R> for Sampled<P> {
+    fn publish(&self, result: &ExperimentResult<R>) -> Result<(), PublishError> {
+        if self.keep(result) {
+            return self.publisher.publish(result);
+        }
+        return Ok(());
+    }
+
+    fn publish_batch(&self, results: &[ExperimentResult<R>]) -> Result<(), PublishError> {
+        return publish_kept(&self.publisher, results, |result| self.keep(result));
+    }
+}
+
+/// Converts results before handing them to another publisher, for example to publish results of
+/// one type to a publisher of another.
+///
+/// ```
+/// use victors::{publishers::Map, result_publisher::NoopPublisher, ExperimentResult};
+///
+/// let publisher = Map::new(NoopPublisher, |result: &ExperimentResult<i32>| {
+///     result.map_values(|value| Some(value.to_string()))
+/// });
+/// ```
+pub struct Map<P, F, T> {
+    publisher: P,
+    f: F,
+    phantom: PhantomData<fn() -> T>,
+}
+
+impl<P, F, T> Map<P, F, T> {
+    /// # Arguments
+    /// * `publisher` - the publisher to publish converted results to
+    /// * `f` - converts each result
+    pub fn new<R>(publisher: P, f: F) -> Self
+    where
+        R: Serialize,
+        T: Serialize,
+        F: Fn(&ExperimentResult<R>) -> ExperimentResult<T>,
+    {
+        return Self {
+            publisher,
+            f,
+            phantom: PhantomData,
+        };
+    }
+}
+
+impl<R, T, P, F> Publisher<R> for Map<P, F, T>
+where
+    R: Serialize,
+    T: Serialize,
+    P: Publisher<T>,
+    F: Fn(&ExperimentResult<R>) -> ExperimentResult<T>,
+{
+    fn publish(&self, result: &ExperimentResult<R>) -> Result<(), PublishError> {
+        return self.publisher.publish(&(self.f)(result));
+    }
+
+    fn publish_batch(&self, results: &[ExperimentResult<R>]) -> Result<(), PublishError> {
+        let mapped: Vec<ExperimentResult<T>> = results.iter().map(|result| (self.f)(result)).collect();
+        return self.publisher.publish_batch(&mapped);
+    }
+}
+
+/// Publishes the results of a batch `keep` returns true for, without cloning them. Each run of
+/// consecutive kept results is handed on as a batch of its own. Stops at the first error.
+fn publish_kept<R, P, F>(publisher: &P, results: &[ExperimentResult<R>], keep: F) -> Result<(), PublishError>
+where
+    R: Serialize,
+    P: Publisher<R>,
+    F: Fn(&ExperimentResult<R>) -> bool,
+{
+    let mut start = 0;
+    for (i, result) in results.iter().enumerate() {
+        if !keep(result) {
+            if start < i {
+                publisher.publish_batch(&results[start..i])?;
+            }
+            start = i + 1;
+        }
+    }
+    if start < results.len() {
+        publisher.publish_batch(&results[start..])?;
+    }
+    return Ok(());
+}
+
+/// Drives every result and returns the first error, if any
+fn first_error<I: Iterator<Item = Result<(), PublishError>>>(results: I) -> Result<(), PublishError> {
+    let mut first = Ok(());
+    for result in results {
+        if first.is_ok() {
+            first = result;
+        }
+    }
+    return first;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use crate::{
+        errors::{ErrorAction, PublishError, VictorsErrors},
+        publishers::combinators::{FanOut, Filter, Map, Sampled},
+        result_publisher::{InMemoryPublisher, ScientistValue},
+        Experiment, ExperimentResult, Publisher,
+    };
+
+    fn run<P: Publisher<i32>>(publisher: P, control: i32, candidate: i32) {
+        let mut experiment = Experiment::new("combinators");
+        experiment.control(move || control).unwrap();
+        experiment.candidate(move || candidate).unwrap();
+        experiment.result_publisher(publisher);
+        experiment.run().unwrap();
+    }
+
+    #[test]
+    fn should_publish_to_every_publisher() {
+        let first: RefCell<Vec<ExperimentResult<i32>>> = RefCell::new(vec![]);
+        let second: RefCell<Vec<ExperimentResult<i32>>> = RefCell::new(vec![]);
+
+        let publisher = FanOut::new()
+            .with(InMemoryPublisher::new(|result| first.borrow_mut().push(result.clone())))
+            .with(InMemoryPublisher::new(|result| second.borrow_mut().push(result.clone())));
+        run(&publisher, 1, 2);
+
+        assert_eq!(1, first.borrow().len());
+        assert_eq!(1, second.borrow().len());
+    }
+
+    #[test]
+    fn should_publish_to_remaining_publishers_when_one_fails() {
+        struct Failing;
+        impl Publisher<i32> for Failing {
+            fn publish(&self, _result: &ExperimentResult<i32>) -> Result<(), PublishError> {
+                return Err("unavailable".into());
+            }
+        }
+        let published: RefCell<Vec<ExperimentResult<i32>>> = RefCell::new(vec![]);
+
+        let publisher = FanOut::new()
+            .with(Failing)
+            .with(InMemoryPublisher::new(|result| published.borrow_mut().push(result.clone())));
+        let mut experiment = Experiment::new("combinators");
+        experiment.control(|| 1).unwrap();
+        experiment.candidate(|| 1).unwrap();
+        experiment.result_publisher(&publisher);
+        experiment.on_error(|_operation, _error| ErrorAction::Raise);
+        let result = experiment.run();
+
+        assert!(matches!(result, Err(VictorsErrors::PublishFailed(_))));
+        assert_eq!(1, published.borrow().len());
+    }
+
+    #[test]
+    fn should_only_publish_results_matching_filter() {
+        let published: RefCell<Vec<ExperimentResult<i32>>> = RefCell::new(vec![]);
+
+        let publisher = Filter::new(
+            InMemoryPublisher::new(|result| published.borrow_mut().push(result.clone())),
+            |result: &ExperimentResult<i32>| result.has_mismatches(),
+        );
+        run(&publisher, 1, 1);
+        run(&publisher, 1, 2);
+
+        let published = published.borrow();
+        assert_eq!(1, published.len());
+        assert!(published[0].has_mismatches());
+    }
+
+    #[test]
+    fn should_hand_runs_of_kept_results_on_as_batches() {
+        struct Batches(RefCell<Vec<usize>>);
+        impl Publisher<i32> for Batches {
+            fn publish(&self, _result: &ExperimentResult<i32>) -> Result<(), PublishError> {
+                self.0.borrow_mut().push(1);
+                return Ok(());
+            }
+
+            fn publish_batch(&self, results: &[ExperimentResult<i32>]) -> Result<(), PublishError> {
+                self.0.borrow_mut().push(results.len());
+                return Ok(());
+            }
+        }
+        let results: RefCell<Vec<ExperimentResult<i32>>> = RefCell::new(vec![]);
+        for candidate in [2, 2, 1, 2, 1, 1, 2] {
+            run(InMemoryPublisher::new(|result| results.borrow_mut().push(result.clone())), 1, candidate);
+        }
+
+        let batches = Batches(RefCell::new(vec![]));
+        Filter::new(&batches, |result: &ExperimentResult<i32>| result.has_mismatches())
+            .publish_batch(&results.borrow())
+            .unwrap();
+
+        assert_eq!(vec![2, 1, 1], *batches.0.borrow());
+    }
+
+    #[test]
+    fn should_publish_every_mismatch_and_no_matches_at_zero_percent() {
+        let published: RefCell<Vec<ExperimentResult<i32>>> = RefCell::new(vec![]);
+
+        let publisher = Sampled::new(InMemoryPublisher::new(|result| published.borrow_mut().push(result.clone())), 0.0);
+        for _ in 0..10 {
+            run(&publisher, 1, 1);
+            run(&publisher, 1, 2);
+        }
+
+        let published = published.borrow();
+        assert_eq!(10, published.len());
+        assert!(published.iter().all(|result| result.has_mismatches()));
+    }
+
+    #[test]
+    fn should_publish_every_match_at_one_hundred_percent() {
+        let published: RefCell<Vec<ExperimentResult<i32>>> = RefCell::new(vec![]);
+
+        let publisher = Sampled::new(InMemoryPublisher::new(|result| published.borrow_mut().push(result.clone())), 100.0);
+        for _ in 0..10 {
+            run(&publisher, 1, 1);
+        }
+
+        assert_eq!(10, published.borrow().len());
+    }
+
+    #[test]
+    fn should_publish_mapped_results() {
+        let published: RefCell<Vec<ExperimentResult<ScientistValue>>> = RefCell::new(vec![]);
+
+        let publisher = Map::new(
+            InMemoryPublisher::new(|result| published.borrow_mut().push(result.clone())),
+            |result: &ExperimentResult<i32>| result.erase(),
+        );
+        run(&publisher, 1, 2);
+
+        let published = published.borrow();
+        assert_eq!(Some(1), published[0].control().unwrap().value.as_ref().unwrap().as_i64());
+    }
+
+    #[test]
+    fn should_compose_publishers() {
+        let mismatches: RefCell<Vec<ExperimentResult<i32>>> = RefCell::new(vec![]);
+        let all: RefCell<Vec<ExperimentResult<i32>>> = RefCell::new(vec![]);
+
+        let publisher = FanOut::new()
+            .with(Filter::new(
+                InMemoryPublisher::new(|result| mismatches.borrow_mut().push(result.clone())),
+                |result: &ExperimentResult<i32>| result.has_mismatches(),
+            ))
+            .with(InMemoryPublisher::new(|result| all.borrow_mut().push(result.clone())));
+        run(&publisher, 1, 1);
+        run(&publisher, 1, 2);
+
+        assert_eq!(1, mismatches.borrow().len());
+        assert_eq!(2, all.borrow().len());
+    }
+}
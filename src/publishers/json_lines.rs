@@ -0,0 +1,392 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use crate::{errors::PublishError, experiment_result::ExperimentResult, result_publisher::Publisher};
+
+/// When a [JsonLinesPublisher] starts a new file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rotation {
+    /// Keep appending to the same file
+    Never,
+    /// Start a new file before a result would grow the current one past this many bytes
+    Size(u64),
+    /// Start a new file once results have been written to the current one for this long
+    Interval(Duration),
+}
+
+/// When a [JsonLinesPublisher] flushes buffered results to disk.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FlushPolicy {
+    /// Flush after every result
+    EveryResult,
+    /// Flush after every given number of results
+    EveryResults(usize),
+    /// Flush only when the buffer fills up, the file is rotated or the publisher is dropped
+    Buffered,
+}
+
+struct FileState {
+    writer: BufWriter<File>,
+    bytes_written: u64,
+    opened_at: Instant,
+    unflushed: usize,
+}
+
+/// Appends each result, serialized as JSON, as a line to a file.
+///
+/// Rotated files are renamed by appending an increasing number to the path, for example
+/// `results.jsonl.1`, `results.jsonl.2`, and a new file is started at the original path. Empty
+/// files are never rotated. Rotated files are kept forever unless [JsonLinesPublisher::max_files]
+/// is set.
+pub struct JsonLinesPublisher {
+    path: PathBuf,
+    rotation: Rotation,
+    max_files: Option<usize>,
+    flush_policy: FlushPolicy,
+    state: Mutex<FileState>,
+}
+
+impl JsonLinesPublisher {
+    /// Creates a publisher appending to the file at `path`, creating it if it doesn't exist.
+    ///
+    /// Defaults to never rotating and flushing after every result.
+    ///
+    /// # Arguments
+    /// * `path` - the file to append results to
+    pub fn new<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let state = JsonLinesPublisher::open(&path)?;
+        return Ok(Self {
+            path,
+            rotation: Rotation::Never,
+            max_files: None,
+            flush_policy: FlushPolicy::EveryResult,
+            state: Mutex::new(state),
+        });
+    }
+
+    /// Set when to start a new file
+    pub fn rotation(mut self, rotation: Rotation) -> Self {
+        self.rotation = rotation;
+        return self;
+    }
+
+    /// Keep at most `max_files` rotated files, deleting the oldest ones when rotating.
+    /// By default rotated files are never deleted. Files which can't be deleted are logged and left behind.
+    pub fn max_files(mut self, max_files: usize) -> Self {
+        self.max_files = Some(max_files);
+        return self;
+    }
+
+    /// Set when to flush buffered results to disk
+    pub fn flush_policy(mut self, flush_policy: FlushPolicy) -> Self {
+        self.flush_policy = flush_policy;
+        return self;
+    }
+
+    /// Returns the path of the file currently being written to
+    pub fn path(&self) -> &Path {
+        return &self.path;
+    }
+
+    /// Flush buffered results to disk
+    pub fn flush(&self) -> io::Result<()> {
+        let mut state = self.state.lock().expect("json lines publisher state poisoned");
+        state.unflushed = 0;
+        return state.writer.flush();
+    }
+
+    /// Serialize the result as a line and append it to the file, rotating first if needed.
+    pub fn write<R: Serialize>(&self, result: &ExperimentResult<R>) -> io::Result<()> {
+        let mut line = serde_json::to_vec(result)?;
+        line.push(b'\n');
+
+        let mut state = self.state.lock().expect("json lines publisher state poisoned");
+        if self.should_rotate(&state, line.len() as u64) {
+            state.writer.flush()?;
+            self.rotate(&mut state)?;
+        }
+        if state.bytes_written == 0 {
+            // the interval counts from the first result, not from when the file was opened
+            state.opened_at = Instant::now();
+        }
+
+        state.writer.write_all(&line)?;
+        state.bytes_written += line.len() as u64;
+        state.unflushed += 1;
+
+        let flush = match self.flush_policy {
+            FlushPolicy::EveryResult => true,
+            FlushPolicy::EveryResults(count) => state.unflushed >= count,
+            FlushPolicy::Buffered => false,
+        };
+        if flush {
+            state.unflushed = 0;
+            state.writer.flush()?;
+        }
+        return Ok(());
+    }
+
+    fn should_rotate(&self, state: &FileState, line_length: u64) -> bool {
+        if state.bytes_written == 0 {
+            return false;
+        }
+        return match self.rotation {
+            Rotation::Never => false,
+            // a result bigger than the limit still gets a file of its own
+            Rotation::Size(max_bytes) => state.bytes_written + line_length > max_bytes,
+            Rotation::Interval(interval) => state.opened_at.elapsed() >= interval,
+        };
+    }
+
+    fn open(path: &Path) -> io::Result<FileState> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let bytes_written = file.metadata()?.len();
+        return Ok(FileState {
+            writer: BufWriter::new(file),
+            bytes_written,
+            opened_at: Instant::now(),
+            unflushed: 0,
+        });
+    }
+
+    /// Moves the current file aside and opens a new one in its place, then deletes the oldest
+    /// rotated files past `max_files`
+    fn rotate(&self, state: &mut FileState) -> io::Result<()> {
+        let mut indexes = JsonLinesPublisher::rotated_indexes(&self.path)?;
+        let next = indexes.last().map_or(1, |index| index + 1);
+        fs::rename(&self.path, JsonLinesPublisher::rotated_path(&self.path, next))?;
+        // reopen before pruning so results never go to the file that was just moved aside
+        *state = JsonLinesPublisher::open(&self.path)?;
+        indexes.push(next);
+
+        if let Some(max_files) = self.max_files {
+            let excess = indexes.len().saturating_sub(max_files);
+            for index in &indexes[..excess] {
+                let rotated = JsonLinesPublisher::rotated_path(&self.path, *index);
+                if let Err(error) = fs::remove_file(&rotated) {
+                    log::warn!("failed to delete rotated file {}: {}", rotated.display(), error);
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    /// Returns the numbers of the rotated files of `path`, oldest first
+    fn rotated_indexes(path: &Path) -> io::Result<Vec<u64>> {
+        let prefix = match path.file_name() {
+            Some(name) => format!("{}.", name.to_string_lossy()),
+            None => return Ok(vec![]),
+        };
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+
+        let mut indexes = vec![];
+        for entry in fs::read_dir(dir)? {
+            let name = entry?.file_name();
+            let index = name.to_str().and_then(|name| name.strip_prefix(&prefix)).and_then(|index| index.parse().ok());
+            if let Some(index) = index {
+                indexes.push(index);
+            }
+        }
+        indexes.sort_unstable();
+        return Ok(indexes);
+    }
+
+    fn rotated_path(path: &Path, index: u64) -> PathBuf {
+        let mut rotated = path.as_os_str().to_owned();
+        rotated.push(format!(".{}", index));
+        return PathBuf::from(rotated);
+    }
+}
+
+impl<R: Serialize> Publisher<R> for JsonLinesPublisher {
+    fn publish(&self, result: &ExperimentResult<R>) -> Result<(), PublishError> {
+        return Ok(self.write(result)?);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+        sync::Arc,
+        thread,
+        time::Duration,
+    };
+
+    use serde_json::Value;
+
+    use crate::{
+        publishers::json_lines::{FlushPolicy, JsonLinesPublisher, Rotation},
+        Experiment,
+    };
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("victors-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        return dir;
+    }
+
+    fn run_experiment(publisher: &Arc<JsonLinesPublisher>, runs: usize) {
+        let mut experiment = Experiment::new("json lines");
+        experiment.control(|| 1).unwrap();
+        experiment.candidate(|| 2).unwrap();
+        experiment.result_publisher(Arc::clone(publisher));
+        for _ in 0..runs {
+            experiment.run().unwrap();
+        }
+    }
+
+    fn read_lines(path: &Path) -> Vec<Value> {
+        return fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+    }
+
+    #[test]
+    fn should_append_each_result_as_a_json_line() {
+        let dir = test_dir("append");
+        let path = dir.join("results.jsonl");
+        let publisher = Arc::new(JsonLinesPublisher::new(&path).unwrap());
+
+        run_experiment(&publisher, 2);
+        run_experiment(&publisher, 1);
+
+        let lines = read_lines(&path);
+        assert_eq!(3, lines.len());
+        assert_eq!("json lines", lines[0]["experiment_name"]);
+        assert_eq!(2, lines[0]["observations"].as_array().unwrap().len());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn should_rotate_files_by_size() {
+        let dir = test_dir("size");
+        let path = dir.join("results.jsonl");
+        let publisher = Arc::new(JsonLinesPublisher::new(&path).unwrap().rotation(Rotation::Size(1)));
+
+        run_experiment(&publisher, 3);
+
+        assert_eq!(1, read_lines(&path).len());
+        assert_eq!(1, read_lines(&dir.join("results.jsonl.1")).len());
+        assert_eq!(1, read_lines(&dir.join("results.jsonl.2")).len());
+        assert!(!dir.join("results.jsonl.3").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn should_rotate_files_by_interval() {
+        let dir = test_dir("interval");
+        let path = dir.join("results.jsonl");
+        let publisher = Arc::new(
+            JsonLinesPublisher::new(&path)
+                .unwrap()
+                .rotation(Rotation::Interval(Duration::from_millis(50))),
+        );
+
+        run_experiment(&publisher, 2);
+        thread::sleep(Duration::from_millis(60));
+        run_experiment(&publisher, 1);
+
+        assert_eq!(2, read_lines(&dir.join("results.jsonl.1")).len());
+        assert_eq!(1, read_lines(&path).len());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn should_not_rotate_empty_files() {
+        let dir = test_dir("interval-empty");
+        let path = dir.join("results.jsonl");
+        let publisher = Arc::new(
+            JsonLinesPublisher::new(&path)
+                .unwrap()
+                .rotation(Rotation::Interval(Duration::from_millis(20))),
+        );
+
+        thread::sleep(Duration::from_millis(30));
+        run_experiment(&publisher, 2);
+
+        assert_eq!(2, read_lines(&path).len());
+        assert!(!dir.join("results.jsonl.1").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn should_delete_oldest_rotated_files_past_max_files() {
+        let dir = test_dir("max-files");
+        let path = dir.join("results.jsonl");
+        let publisher = Arc::new(
+            JsonLinesPublisher::new(&path)
+                .unwrap()
+                .rotation(Rotation::Size(1))
+                .max_files(2),
+        );
+
+        run_experiment(&publisher, 5);
+
+        assert_eq!(1, read_lines(&path).len());
+        assert!(!dir.join("results.jsonl.1").exists());
+        assert!(!dir.join("results.jsonl.2").exists());
+        assert_eq!(1, read_lines(&dir.join("results.jsonl.3")).len());
+        assert_eq!(1, read_lines(&dir.join("results.jsonl.4")).len());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn should_keep_writing_to_the_new_file_when_deleting_rotated_files_fails() {
+        let dir = test_dir("max-files-undeletable");
+        let path = dir.join("results.jsonl");
+        // a directory can't be removed as a file
+        fs::create_dir(dir.join("results.jsonl.1")).unwrap();
+        let publisher = Arc::new(
+            JsonLinesPublisher::new(&path)
+                .unwrap()
+                .rotation(Rotation::Size(1))
+                .max_files(1),
+        );
+
+        run_experiment(&publisher, 3);
+
+        assert_eq!(1, read_lines(&path).len());
+        assert!(dir.join("results.jsonl.1").is_dir());
+        assert!(!dir.join("results.jsonl.2").exists());
+        assert_eq!(1, read_lines(&dir.join("results.jsonl.3")).len());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn should_only_flush_after_configured_number_of_results() {
+        let dir = test_dir("flush");
+        let path = dir.join("results.jsonl");
+        let publisher = Arc::new(
+            JsonLinesPublisher::new(&path)
+                .unwrap()
+                .flush_policy(FlushPolicy::EveryResults(2)),
+        );
+
+        run_experiment(&publisher, 1);
+        assert_eq!(0, read_lines(&path).len());
+
+        run_experiment(&publisher, 1);
+        assert_eq!(2, read_lines(&path).len());
+
+        run_experiment(&publisher, 1);
+        publisher.flush().unwrap();
+        assert_eq!(3, read_lines(&path).len());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,11 @@
+//! Built in [crate::Publisher] implementations.
+
+pub mod buffered;
+pub mod combinators;
+pub mod json_lines;
+
+pub use crate::publishers::{
+    buffered::{BufferedPublisher, Overflow},
+    combinators::{FanOut, Filter, Map, Sampled},
+    json_lines::{FlushPolicy, JsonLinesPublisher, Rotation},
+};
@@ -1,110 +1,206 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::marker::PhantomData;
+use std::sync::Arc;
 use serde::Serialize;
 
-use crate::experiment_result::ExperimentResult;
+use crate::{errors::PublishError, experiment_result::ExperimentResult};
 
-// https://github.com/ex0dus-0x/structmap/blob/master/src/value.rs
-
-
-// serde
-// ScientistValue / PublisherValue /
-pub enum Value {
+/// An observed value with its type erased, similar to [serde_json::Value].
+///
+/// Every [Serialize] value can be converted into one, which lets a single [Publisher] handle
+/// experiments of any return type. See [ExperimentResult::erase] and [ErasedPublisher].
+///
+/// Object keys are kept sorted, so the same value always serializes the same way.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum ScientistValue {
     Null,
     Bool(bool),
     Number(Number),
     String(String),
-    Array(Vec<Value>),
-    Object(HashMap<String, Value>),
+    Array(Vec<ScientistValue>),
+    Object(BTreeMap<String, ScientistValue>),
 }
 
 /// Represents the numeric primitive types that are supported for conversion.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
 pub enum Number {
     I64(i64),
     U64(u64),
     F64(f64),
 }
 
+impl ScientistValue {
+    /// Convert any serializable value into a ScientistValue
+    ///
+    /// # Arguments
+    /// * `value` - the value to convert
+    ///
+    /// # Return
+    /// Returns an error when the value can not be serialized, for example a map with non string keys
+    pub fn from_serialize<T: Serialize + ?Sized>(value: &T) -> serde_json::Result<Self> {
+        return Ok(ScientistValue::from(serde_json::to_value(value)?));
+    }
+
+    pub fn is_null(&self) -> bool {
+        return matches!(self, ScientistValue::Null);
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        return match self {
+            ScientistValue::Bool(b) => Some(*b),
+            _ => None,
+        };
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        return match self {
+            ScientistValue::String(s) => Some(s),
+            _ => None,
+        };
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        return match self {
+            ScientistValue::Number(Number::I64(n)) => Some(*n),
+            ScientistValue::Number(Number::U64(n)) => i64::try_from(*n).ok(),
+            _ => None,
+        };
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        return match self {
+            ScientistValue::Number(Number::U64(n)) => Some(*n),
+            ScientistValue::Number(Number::I64(n)) => u64::try_from(*n).ok(),
+            _ => None,
+        };
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        return match self {
+            ScientistValue::Number(Number::I64(n)) => Some(*n as f64),
+            ScientistValue::Number(Number::U64(n)) => Some(*n as f64),
+            ScientistValue::Number(Number::F64(n)) => Some(*n),
+            _ => None,
+        };
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<ScientistValue>> {
+        return match self {
+            ScientistValue::Array(values) => Some(values),
+            _ => None,
+        };
+    }
+
+    pub fn as_object(&self) -> Option<&BTreeMap<String, ScientistValue>> {
+        return match self {
+            ScientistValue::Object(map) => Some(map),
+            _ => None,
+        };
+    }
+}
+
+impl From<serde_json::Value> for ScientistValue {
+    fn from(value: serde_json::Value) -> Self {
+        return match value {
+            serde_json::Value::Null => ScientistValue::Null,
+            serde_json::Value::Bool(b) => ScientistValue::Bool(b),
+            serde_json::Value::Number(n) => ScientistValue::Number(Number::from(n)),
+            serde_json::Value::String(s) => ScientistValue::String(s),
+            serde_json::Value::Array(values) => {
+                ScientistValue::Array(values.into_iter().map(ScientistValue::from).collect())
+            }
+            serde_json::Value::Object(map) => {
+                ScientistValue::Object(map.into_iter().map(|(k, v)| (k, ScientistValue::from(v))).collect())
+            }
+        };
+    }
+}
+
+impl From<serde_json::Number> for Number {
+    fn from(n: serde_json::Number) -> Self {
+        if let Some(u) = n.as_u64() {
+            return Number::U64(u);
+        }
+        if let Some(i) = n.as_i64() {
+            return Number::I64(i);
+        }
+        // serde_json numbers are always one of u64, i64 or f64
+        return Number::F64(n.as_f64().unwrap_or(f64::NAN));
+    }
+}
+
+pub trait Publisher<R: Serialize> {
+    /// Publish the result of running an experiment.
+    ///
+    /// Errors are handed to the experiment's error handler, see [crate::Experiment::on_error],
+    /// which logs them by default.
+    fn publish(&self, result: &ExperimentResult<R>) -> Result<(), PublishError>;
+
+    /// Publish several results at once. Publishes each result in turn by default, stopping at the
+    /// first error. Publishers able to write many results more cheaply than one at a time should
+    /// override it.
+    fn publish_batch(&self, results: &[ExperimentResult<R>]) -> Result<(), PublishError> {
+        for result in results {
+            self.publish(result)?;
+        }
+        return Ok(());
+    }
+}
+
+/// Lets experiments publish to a publisher they don't own
+impl<R: Serialize, P: Publisher<R> + ?Sized> Publisher<R> for &P {
+    fn publish(&self, result: &ExperimentResult<R>) -> Result<(), PublishError> {
+        return (**self).publish(result);
+    }
+
+    fn publish_batch(&self, results: &[ExperimentResult<R>]) -> Result<(), PublishError> {
+        return (**self).publish_batch(results);
+    }
+}
+
+/// Lets several experiments share one publisher
+impl<R: Serialize, P: Publisher<R> + ?Sized> Publisher<R> for Arc<P> {
+    fn publish(&self, result: &ExperimentResult<R>) -> Result<(), PublishError> {
+        return (**self).publish(result);
+    }
 
-// #[inline]
-// pub fn to_string<T>(value: &T) -> Result<String>
-//     where
-//         T: ?Sized + Serialize,
-// {
-//     let vec = tri!(to_vec(value));
-//     let string = unsafe {
-//         // We do not emit invalid UTF-8.
-//         String::from_utf8_unchecked(vec)
-//     };
-//     Ok(string)
-// }
-
-// #[inline]
-// pub fn to_vec<T>(value: &T) -> Result<Vec<u8>>
-//     where
-//         T: ?Sized + Serialize,
-// {
-//     let mut writer = Vec::with_capacity(128);
-//     tri!(to_writer(&mut writer, value));
-//     Ok(writer)
-// }
-
-
-// https://github.com/open-telemetry/opentelemetry-rust/blob/f20c9b40547ee20b6ec99414bb21abdd3a54d99b/opentelemetry-api/src/common.rs
-
-// #[derive(Clone, Debug, PartialEq)]
-// pub enum Value {
-//     /// bool values
-//     Bool(bool),
-//     I8(i8),
-//     U8(u8),
-//     I16(i16),
-//     U16(u16),
-//     I32(i32),
-//     U32(u32),
-//     I64(i64),
-//     U64(u64),
-//     I128(i128),
-//     U128(u128),
-//     ISize(isize),
-//     USize(usize),
-//     /// i64 values
-//     I64(i64),
-//     /// f32 values
-//     F32(f32),
-//     /// f64 values
-//     F64(f64),
-//     /// String values
-//     String(StringValue),
-//     /// Array of homogeneous values
-//     Array(Array),
-// }
-
-
-// #[derive(Clone, Debug, PartialEq)]
-// pub enum Array {
-//     /// Array of bools
-//     Bool(Vec<bool>),
-//     /// Array of integers
-//     I64(Vec<i64>),
-//     /// Array of floats
-//     F64(Vec<f64>),
-//     /// Array of strings
-//     String(Vec<StringValue>),
-// }
-
-
-pub trait Publisher<R: Clone + PartialEq + Serialize> {
-    fn publish(&self, result: &ExperimentResult<R>);
+    fn publish_batch(&self, results: &[ExperimentResult<R>]) -> Result<(), PublishError> {
+        return (**self).publish_batch(results);
+    }
 }
 
 pub struct NoopPublisher;
-impl<R: Clone + PartialEq + Serialize> Publisher<R> for NoopPublisher {
-    fn publish(&self, _result: &ExperimentResult<R>) {}
+impl<R: Serialize> Publisher<R> for NoopPublisher {
+    fn publish(&self, _result: &ExperimentResult<R>) -> Result<(), PublishError> {
+        return Ok(());
+    }
+}
+
+/// Adapts a publisher of [ScientistValue] results so it can publish results of any experiment.
+pub struct ErasedPublisher<P: Publisher<ScientistValue>> {
+    publisher: P,
+}
+
+impl<P: Publisher<ScientistValue>> ErasedPublisher<P> {
+    pub fn new(publisher: P) -> Self {
+        Self { publisher }
+    }
+
+    /// Returns the wrapped publisher
+    pub fn into_inner(self) -> P {
+        return self.publisher;
+    }
 }
 
-pub(crate) struct InMemoryPublisher<R: Clone + PartialEq + Serialize, CB>
+impl<R: Serialize, P: Publisher<ScientistValue>> Publisher<R> for ErasedPublisher<P> {
+    fn publish(&self, result: &ExperimentResult<R>) -> Result<(), PublishError> {
+        return self.publisher.publish(&result.erase());
+    }
+}
+
+pub(crate) struct InMemoryPublisher<R: Serialize, CB>
 where
     CB: FnOnce(&ExperimentResult<R>) + Copy,
 {
@@ -112,7 +208,7 @@ where
     pub cb: CB,
 }
 
-impl<R: Clone + PartialEq + Serialize, CB> InMemoryPublisher<R, CB>
+impl<R: Serialize, CB> InMemoryPublisher<R, CB>
 where
     CB: FnOnce(&ExperimentResult<R>) + Copy,
 {
@@ -124,11 +220,114 @@ where
     }
 }
 
-impl<R: Clone + PartialEq + Serialize, CB> Publisher<R> for InMemoryPublisher<R, CB>
+impl<R: Serialize, CB> Publisher<R> for InMemoryPublisher<R, CB>
 where
     CB: FnOnce(&ExperimentResult<R>) + Copy,
 {
-    fn publish(&self, result: &ExperimentResult<R>) {
+    fn publish(&self, result: &ExperimentResult<R>) -> Result<(), PublishError> {
         (self.cb)(result);
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use serde::Serialize;
+    use serde_json::json;
+
+    use crate::{
+        result_publisher::{ErasedPublisher, InMemoryPublisher, Number, ScientistValue},
+        Experiment, ExperimentResult,
+    };
+
+    #[derive(Clone, PartialEq, Serialize)]
+    struct Widget {
+        id: u32,
+        name: String,
+        tags: Vec<String>,
+        weight: Option<f64>,
+    }
+
+    #[test]
+    fn should_convert_serializable_values() {
+        let widget = Widget {
+            id: 7,
+            name: "gear".to_string(),
+            tags: vec!["metal".to_string()],
+            weight: None,
+        };
+
+        let value = ScientistValue::from_serialize(&widget).unwrap();
+
+        let object = value.as_object().unwrap();
+        assert_eq!(Some(7), object["id"].as_u64());
+        assert_eq!(Some("gear"), object["name"].as_str());
+        assert_eq!(
+            &vec![ScientistValue::String("metal".to_string())],
+            object["tags"].as_array().unwrap()
+        );
+        assert!(object["weight"].is_null());
+    }
+
+    #[test]
+    fn should_convert_numbers() {
+        assert_eq!(ScientistValue::Number(Number::U64(3)), ScientistValue::from_serialize(&3_i32).unwrap());
+        assert_eq!(ScientistValue::Number(Number::I64(-3)), ScientistValue::from_serialize(&-3_i64).unwrap());
+        assert_eq!(ScientistValue::Number(Number::F64(1.5)), ScientistValue::from_serialize(&1.5_f32).unwrap());
+        assert_eq!(Some(-3.0), ScientistValue::from_serialize(&-3_i64).unwrap().as_f64());
+    }
+
+    #[test]
+    fn should_fail_to_convert_maps_with_non_string_keys() {
+        let mut map = HashMap::new();
+        map.insert((1, 2), "value");
+
+        assert!(ScientistValue::from_serialize(&map).is_err());
+    }
+
+    #[test]
+    fn should_serialize_like_the_original_value() {
+        let original = json!({ "id": 1, "tags": ["a", "b"], "ratio": 0.5, "active": true, "parent": null });
+
+        let value = ScientistValue::from(original.clone());
+
+        assert_eq!(original, serde_json::to_value(&value).unwrap());
+    }
+
+    #[test]
+    fn should_serialize_object_keys_in_sorted_order() {
+        let value = ScientistValue::from(json!({ "zebra": 1, "apple": 2, "mango": 3 }));
+
+        assert_eq!(r#"{"apple":2,"mango":3,"zebra":1}"#, serde_json::to_string(&value).unwrap());
+    }
+
+    #[test]
+    fn should_publish_results_of_any_type_with_one_publisher() {
+        let r: RefCell<Vec<ExperimentResult<ScientistValue>>> = RefCell::new(vec![]);
+
+        let mut numbers = Experiment::new("numbers");
+        numbers.control(|| 1).unwrap();
+        numbers.candidate(|| 2).unwrap();
+        numbers.result_publisher(ErasedPublisher::new(InMemoryPublisher::new(|result| {
+            r.borrow_mut().push(result.clone());
+        })));
+        numbers.run().unwrap();
+
+        let mut strings = Experiment::new("strings");
+        strings.control(|| "a".to_string()).unwrap();
+        strings.candidate(|| "a".to_string()).unwrap();
+        strings.result_publisher(ErasedPublisher::new(InMemoryPublisher::new(|result| {
+            r.borrow_mut().push(result.clone());
+        })));
+        strings.run().unwrap();
+
+        let results = r.take();
+        assert_eq!(2, results.len());
+        assert!(results[0].has_mismatches());
+        assert_eq!(Some(1), results[0].control().unwrap().value.as_ref().unwrap().as_i64());
+        assert!(results[1].matched());
+        assert_eq!(Some("a"), results[1].control().unwrap().value.as_ref().unwrap().as_str());
     }
 }
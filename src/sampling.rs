@@ -0,0 +1,363 @@
+use std::cell::RefCell;
+
+use rand::{rngs::ThreadRng, thread_rng, Rng, RngCore};
+use serde_json::Value;
+
+use crate::context::Context;
+
+/// Decides which runs of an experiment run their candidates. Used to ramp up an experiment.
+pub trait Sampler {
+    /// Should this run of the experiment run its candidates?
+    ///
+    /// # Arguments
+    /// * `experiment_name` - name of the experiment being run
+    /// * `context` - the experiment context
+    fn sample(&self, experiment_name: &str, context: &Context) -> bool;
+
+    /// Fraction of runs, between 0 and 1, that this sampler lets through.
+    ///
+    /// Recorded on each published [crate::ExperimentResult] so that totals can be extrapolated.
+    fn rate(&self) -> f64;
+}
+
+/// Samples a random percentage of runs.
+pub struct PercentSampler<G: RngCore = ThreadRng> {
+    percent: f64,
+    rng: RefCell<G>,
+}
+
+impl PercentSampler<ThreadRng> {
+    /// Creates a sampler that lets through `percent` percent of runs, using the thread local RNG.
+    ///
+    /// # Arguments
+    /// * `percent` - between 0 and 100, values outside of that range are clamped and NaN is 0
+    pub fn new(percent: f64) -> Self {
+        return PercentSampler::with_rng(percent, thread_rng());
+    }
+}
+
+impl<G: RngCore> PercentSampler<G> {
+    /// Creates a sampler that lets through `percent` percent of runs, using the given RNG.
+    ///
+    /// # Arguments
+    /// * `percent` - between 0 and 100, values outside of that range are clamped and NaN is 0
+    /// * `rng` - the random number generator used to pick runs
+    pub fn with_rng(percent: f64, rng: G) -> Self {
+        return Self {
+            percent: clamp_percent(percent),
+            rng: RefCell::new(rng),
+        };
+    }
+
+    /// Returns the percentage of runs this sampler lets through
+    pub fn percent(&self) -> f64 {
+        return self.percent;
+    }
+}
+
+impl<G: RngCore> Sampler for PercentSampler<G> {
+    fn sample(&self, _experiment_name: &str, _context: &Context) -> bool {
+        if self.percent <= 0.0 {
+            return false;
+        }
+        if self.percent >= 100.0 {
+            return true;
+        }
+        return self.rng.borrow_mut().gen_range(0.0..100.0) < self.percent;
+    }
+
+    fn rate(&self) -> f64 {
+        return self.percent / 100.0;
+    }
+}
+
+/// Clamps a percentage to between 0 and 100. NaN is treated as 0, as it would otherwise never
+/// sample and be recorded as the sampling rate
+pub(crate) fn clamp_percent(percent: f64) -> f64 {
+    if percent.is_nan() {
+        return 0.0;
+    }
+    return percent.clamp(0.0, 100.0);
+}
+
+const BUCKETS: u64 = 10_000;
+
+/// Where a [BucketSampler] gets the key it buckets runs by.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BucketKey {
+    /// The value stored under this key in the experiment context
+    Context(String),
+    /// The given value
+    Value(String),
+}
+
+/// Samples runs by hashing a stable key, such as a user or tenant id, into buckets so that the
+/// same key always gets the same decision.
+///
+/// Keys are salted with the experiment name by default so that different experiments pick
+/// different keys for the same percentage.
+#[derive(Clone, Debug)]
+pub struct BucketSampler {
+    key: BucketKey,
+    percent: f64,
+    salt: Option<String>,
+}
+
+impl BucketSampler {
+    /// Creates a sampler bucketing runs by the value stored under `context_key` in the experiment
+    /// context. Runs whose context lacks the key are not sampled.
+    ///
+    /// # Arguments
+    /// * `context_key` - key of the context value to bucket by
+    /// * `percent` - between 0 and 100, values outside of that range are clamped and NaN is 0
+    pub fn from_context<S: Into<String>>(context_key: S, percent: f64) -> Self {
+        return BucketSampler::new(BucketKey::Context(context_key.into()), percent);
+    }
+
+    /// Creates a sampler bucketing runs by the given key.
+    ///
+    /// # Arguments
+    /// * `key` - the value to bucket by
+    /// * `percent` - between 0 and 100, values outside of that range are clamped and NaN is 0
+    pub fn from_key<S: Into<String>>(key: S, percent: f64) -> Self {
+        return BucketSampler::new(BucketKey::Value(key.into()), percent);
+    }
+
+    fn new(key: BucketKey, percent: f64) -> Self {
+        return Self {
+            key,
+            percent: clamp_percent(percent),
+            salt: None,
+        };
+    }
+
+    /// Salt keys with the given value rather than the experiment name.
+    ///
+    /// Experiments sharing a salt sample the same keys.
+    pub fn salt<S: Into<String>>(mut self, salt: S) -> Self {
+        self.salt = Some(salt.into());
+        return self;
+    }
+
+    /// Returns the percentage of keys this sampler lets through
+    pub fn percent(&self) -> f64 {
+        return self.percent;
+    }
+
+    /// Returns the bucket, between 0 and 9,999, the key falls into for the given experiment.
+    pub fn bucket(&self, experiment_name: &str, key: &str) -> u64 {
+        let salt = self.salt.as_deref().unwrap_or(experiment_name);
+        return fnv1a(&[salt.as_bytes(), b":", key.as_bytes()]) % BUCKETS;
+    }
+
+    fn resolve_key(&self, context: &Context) -> Option<String> {
+        return match &self.key {
+            BucketKey::Value(key) => Some(key.to_string()),
+            BucketKey::Context(context_key) => context.get(context_key).map(|value| match value {
+                Value::String(key) => key.to_string(),
+                value => value.to_string(),
+            }),
+        };
+    }
+}
+
+impl Sampler for BucketSampler {
+    fn sample(&self, experiment_name: &str, context: &Context) -> bool {
+        return match self.resolve_key(context) {
+            None => false,
+            Some(key) => (self.bucket(experiment_name, &key) as f64) < self.percent * (BUCKETS as f64) / 100.0,
+        };
+    }
+
+    fn rate(&self) -> f64 {
+        return self.percent / 100.0;
+    }
+}
+
+// FNV-1a is used rather than std's hasher as its output must not change between releases,
+// otherwise keys would move between buckets.
+fn fnv1a(parts: &[&[u8]]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in parts.iter().flat_map(|part| part.iter()) {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    return hash;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use rand::{rngs::StdRng, SeedableRng};
+    use serde_json::json;
+
+    use crate::{
+        sampling::{BucketSampler, PercentSampler, Sampler},
+        Context, Experiment,
+    };
+
+    #[test]
+    fn should_never_sample_at_zero_percent() {
+        let sampler = PercentSampler::new(0.0);
+
+        assert!((0..1000).all(|_| !sampler.sample("experiment", &Context::new())));
+        assert_eq!(0.0, sampler.rate());
+    }
+
+    #[test]
+    fn should_always_sample_at_one_hundred_percent() {
+        let sampler = PercentSampler::new(100.0);
+
+        assert!((0..1000).all(|_| sampler.sample("experiment", &Context::new())));
+        assert_eq!(1.0, sampler.rate());
+    }
+
+    #[test]
+    fn should_sample_roughly_the_configured_percentage() {
+        let sampler = PercentSampler::with_rng(25.0, StdRng::seed_from_u64(42));
+
+        let sampled = (0..10_000).filter(|_| sampler.sample("experiment", &Context::new())).count();
+
+        assert!((2_250..2_750).contains(&sampled), "sampled {}", sampled);
+        assert_eq!(0.25, sampler.rate());
+    }
+
+    #[test]
+    fn should_clamp_percentage() {
+        assert_eq!(100.0, PercentSampler::new(150.0).percent());
+        assert_eq!(0.0, PercentSampler::new(-5.0).percent());
+    }
+
+    #[test]
+    fn should_treat_nan_percentage_as_zero() {
+        let sampler = PercentSampler::new(f64::NAN);
+
+        assert_eq!(0.0, sampler.percent());
+        assert_eq!(0.0, sampler.rate());
+        assert!(!sampler.sample("experiment", &Context::new()));
+        assert_eq!(0.0, BucketSampler::from_key("user-42", f64::NAN).rate());
+    }
+
+    #[test]
+    fn should_record_zero_sampling_rate_for_nan_percentage() {
+        let mut experiment: Experiment<'_, u32> = Experiment::new("nan");
+        experiment.control(|| 1).unwrap();
+        experiment.candidate(|| 1).unwrap();
+        experiment.percent_enabled(f64::NAN);
+
+        assert_eq!(0.0, experiment.sampling_rate());
+        assert_eq!(1, experiment.run().unwrap());
+    }
+
+    #[test]
+    fn should_always_make_the_same_decision_for_a_key() {
+        let sampler = BucketSampler::from_context("user_id", 50.0);
+
+        for user_id in 0..100 {
+            let context = Context::from_value(json!({ "user_id": user_id })).unwrap();
+            let decision = sampler.sample("experiment", &context);
+            let other_sampler = BucketSampler::from_context("user_id", 50.0);
+            assert!((0..10).all(|_| other_sampler.sample("experiment", &context) == decision));
+        }
+    }
+
+    #[test]
+    fn should_bucket_keys_stably() {
+        let sampler = BucketSampler::from_key("user-42", 10.0);
+
+        assert_eq!(sampler.bucket("experiment", "user-42"), sampler.bucket("experiment", "user-42"));
+        assert_eq!(6_192, sampler.bucket("experiment", "user-42"));
+    }
+
+    #[test]
+    fn should_sample_roughly_the_configured_percentage_of_keys() {
+        let sampler = BucketSampler::from_context("tenant", 20.0);
+
+        let sampled = (0..10_000)
+            .map(|tenant| Context::from_value(json!({ "tenant": format!("tenant-{}", tenant) })).unwrap())
+            .filter(|context| sampler.sample("experiment", context))
+            .count();
+
+        assert!((1_800..2_200).contains(&sampled), "sampled {}", sampled);
+        assert_eq!(0.2, sampler.rate());
+    }
+
+    #[test]
+    fn should_not_sample_when_context_key_is_missing() {
+        let sampler = BucketSampler::from_context("user_id", 100.0);
+
+        assert!(!sampler.sample("experiment", &Context::new()));
+    }
+
+    #[test]
+    fn should_salt_keys_with_experiment_name_by_default() {
+        let sampler = BucketSampler::from_key("user-42", 10.0);
+
+        let buckets: Vec<u64> = ["a", "b", "c", "d"].iter().map(|name| sampler.bucket(name, "user-42")).collect();
+
+        assert!(buckets.iter().any(|bucket| *bucket != buckets[0]));
+    }
+
+    #[test]
+    fn should_use_custom_salt_across_experiments() {
+        let sampler = BucketSampler::from_key("user-42", 10.0).salt("shared");
+
+        assert_eq!(sampler.bucket("first", "user-42"), sampler.bucket("second", "user-42"));
+    }
+
+    /// Runs an experiment sampled by `sampler` for the given user and returns whether the
+    /// candidate ran
+    fn runs_candidate(name: &'static str, sampler: BucketSampler, user_id: u32) -> bool {
+        let ran = Cell::new(false);
+        let context = Context::from_value(json!({ "user_id": user_id })).unwrap();
+
+        let mut experiment: Experiment<'_, u32> = Experiment::new_with_context(name, context);
+        experiment.control(|| 1).unwrap();
+        experiment
+            .candidate(|| {
+                ran.set(true);
+                return 1;
+            })
+            .unwrap();
+        experiment.sampler(sampler);
+        experiment.run().unwrap();
+
+        return ran.get();
+    }
+
+    #[test]
+    fn should_make_the_same_decision_for_a_context_key_across_experiment_runs() {
+        let sampler = BucketSampler::from_context("user_id", 50.0);
+
+        let decisions: Vec<bool> = (0..100).map(|user_id| runs_candidate("first", sampler.clone(), user_id)).collect();
+
+        for (user_id, decision) in decisions.iter().enumerate() {
+            assert!((0..5).all(|_| runs_candidate("first", sampler.clone(), user_id as u32) == *decision));
+        }
+        assert!(decisions.iter().any(|decision| *decision));
+        assert!(decisions.iter().any(|decision| !*decision));
+    }
+
+    #[test]
+    fn should_pick_different_keys_for_experiments_with_different_names() {
+        let sampler = BucketSampler::from_context("user_id", 50.0);
+
+        let differing = (0..100)
+            .filter(|user_id| {
+                runs_candidate("first", sampler.clone(), *user_id) != runs_candidate("second", sampler.clone(), *user_id)
+            })
+            .count();
+
+        assert!(differing > 0);
+    }
+
+    #[test]
+    fn should_pick_the_same_keys_for_experiments_sharing_a_salt() {
+        let sampler = BucketSampler::from_context("user_id", 50.0).salt("shared");
+
+        assert!((0..100).all(|user_id| {
+            runs_candidate("first", sampler.clone(), user_id) == runs_candidate("second", sampler.clone(), user_id)
+        }));
+    }
+}
@@ -0,0 +1,133 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    task::{Context, Poll, Waker},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Future which resolves to `None` when the wrapped future does not complete within the timeout.
+///
+/// The timer runs on a thread of its own so that it works with any async runtime.
+pub(crate) struct Timeout<F> {
+    future: F,
+    delay: Option<Delay>,
+}
+
+impl<F: Future + Unpin> Timeout<F> {
+    /// Wrap `future`, giving up on it after `timeout`. No timeout is applied when `timeout` is None.
+    pub(crate) fn new(future: F, timeout: Option<Duration>) -> Self {
+        Self {
+            future,
+            delay: timeout.map(Delay::new),
+        }
+    }
+}
+
+impl<F: Future + Unpin> Future for Timeout<F> {
+    type Output = Option<F::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if let Poll::Ready(output) = Pin::new(&mut self.future).poll(cx) {
+            return Poll::Ready(Some(output));
+        }
+
+        return match self.delay.as_mut().map(|delay| Pin::new(delay).poll(cx)) {
+            Some(Poll::Ready(())) => Poll::Ready(None),
+            _ => Poll::Pending,
+        };
+    }
+}
+
+/// Number of timer threads still waiting on a deadline
+static LIVE_TIMERS: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the number of timer threads that have not exited yet
+#[cfg(test)]
+pub(crate) fn live_timers() -> usize {
+    return LIVE_TIMERS.load(Ordering::SeqCst);
+}
+
+#[derive(Default)]
+struct DelayState {
+    elapsed: bool,
+    /// set when the delay is dropped before its deadline, so the timer thread can exit early
+    cancelled: bool,
+    waker: Option<Waker>,
+}
+
+type SharedDelayState = Arc<(Mutex<DelayState>, Condvar)>;
+
+/// Future which resolves once the given duration has elapsed since it was first polled.
+///
+/// The timer thread waits on a condvar rather than sleeping, and exits as soon as the delay is
+/// dropped, so a future that completes early doesn't leave a thread behind for the whole timeout.
+struct Delay {
+    duration: Duration,
+    state: Option<SharedDelayState>,
+}
+
+impl Delay {
+    fn new(duration: Duration) -> Self {
+        Self { duration, state: None }
+    }
+
+    fn start(&self) -> SharedDelayState {
+        let state: SharedDelayState = Arc::new((Mutex::new(DelayState::default()), Condvar::new()));
+        let timer_state = Arc::clone(&state);
+        let deadline = Instant::now() + self.duration;
+        LIVE_TIMERS.fetch_add(1, Ordering::SeqCst);
+        thread::spawn(move || {
+            let (lock, condvar) = &*timer_state;
+            let mut state = lock.lock().expect("delay state poisoned");
+            while !state.cancelled {
+                let now = Instant::now();
+                if now >= deadline {
+                    state.elapsed = true;
+                    if let Some(waker) = state.waker.take() {
+                        waker.wake();
+                    }
+                    break;
+                }
+                state = condvar.wait_timeout(state, deadline - now).expect("delay state poisoned").0;
+            }
+            LIVE_TIMERS.fetch_sub(1, Ordering::SeqCst);
+        });
+        return state;
+    }
+}
+
+impl Future for Delay {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.state.is_none() {
+            self.state = Some(self.start());
+        }
+
+        let mut state = self.state.as_ref().unwrap().0.lock().expect("delay state poisoned");
+        if state.elapsed {
+            return Poll::Ready(());
+        }
+        state.waker = Some(cx.waker().clone());
+        return Poll::Pending;
+    }
+}
+
+impl Drop for Delay {
+    fn drop(&mut self) {
+        if let Some(state) = &self.state {
+            let (lock, condvar) = &**state;
+            // a poisoned lock means the timer thread is gone already
+            if let Ok(mut state) = lock.lock() {
+                state.cancelled = true;
+                state.waker = None;
+            }
+            condvar.notify_one();
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use std::{
+    any::Any,
+    backtrace::{Backtrace, BacktraceStatus},
+    cell::{Cell, RefCell},
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    sync::Once,
+    task::{Context, Poll},
+};
+
+// A backtrace can only be captured while the panicking stack is still around, which means from
+// within the panic hook. We install a hook once that chains to whatever hook was previously set
+// and stashes the backtrace in a thread local, but only for panics raised inside `catch`.
+static INSTALL_HOOK: Once = Once::new();
+
+thread_local! {
+    static CATCHING: Cell<bool> = Cell::new(false);
+    static LAST_BACKTRACE: RefCell<Option<Backtrace>> = RefCell::new(None);
+}
+
+/// A panic caught while running a behavior.
+#[derive(Debug)]
+pub(crate) struct CaughtPanic {
+    pub message: String,
+    pub backtrace: Option<String>,
+}
+
+/// Run `f`, catching any panic it raises along with the backtrace of where it panicked.
+///
+/// The backtrace is only captured when backtraces are enabled, see [Backtrace::capture].
+pub(crate) fn catch<F, T>(f: F) -> Result<T, CaughtPanic>
+where
+    F: FnOnce() -> T,
+{
+    install_hook();
+
+    let was_catching = CATCHING.with(|catching| catching.replace(true));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    CATCHING.with(|catching| catching.set(was_catching));
+
+    return result.map_err(|payload| CaughtPanic {
+        message: panic_message(payload.as_ref()),
+        backtrace: LAST_BACKTRACE
+            .with(|backtrace| backtrace.borrow_mut().take())
+            .filter(|backtrace| backtrace.status() == BacktraceStatus::Captured)
+            .map(|backtrace| backtrace.to_string()),
+    });
+}
+
+/// Future which catches any panic raised while polling the wrapped future.
+pub(crate) struct CatchUnwind<F> {
+    future: F,
+}
+
+impl<F: Future + Unpin> CatchUnwind<F> {
+    pub(crate) fn new(future: F) -> Self {
+        Self { future }
+    }
+}
+
+impl<F: Future + Unpin> Future for CatchUnwind<F> {
+    type Output = Result<F::Output, CaughtPanic>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let future = &mut self.future;
+        return match catch(|| Pin::new(future).poll(cx)) {
+            Ok(Poll::Pending) => Poll::Pending,
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Err(panic) => Poll::Ready(Err(panic)),
+        };
+    }
+}
+
+fn install_hook() {
+    INSTALL_HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if CATCHING.with(|catching| catching.get()) {
+                LAST_BACKTRACE.with(|backtrace| *backtrace.borrow_mut() = Some(Backtrace::capture()));
+            }
+            previous(info);
+        }));
+    });
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        return message.to_string();
+    }
+    if let Some(message) = payload.downcast_ref::<String>() {
+        return message.clone();
+    }
+    return "Box<dyn Any>".to_string();
+}
@@ -1,11 +1,17 @@
-use std::any::Any;
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
 use once_cell::sync::Lazy;
-use std::sync::{Arc, RwLock};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, RwLock,
+};
 use serde::Serialize;
-use crate::{errors::VictorsResult, experiment::{Experiment, UncontrolledExperiment}, Publisher};
-use crate::result_publisher::NoopPublisher;
+use crate::{async_experiment::AsyncExperiment, errors::VictorsResult, experiment::{Experiment, UncontrolledExperiment}, ExperimentResult, Publisher};
+use crate::errors::PublishError;
+use crate::result_publisher::ScientistValue;
 
-pub trait Scientist<'a, R: Clone + PartialEq + Serialize> {
+pub trait Scientist<'a, R: Serialize> {
     type P: Publisher<R> + 'a;
 
     /// Define and run a controlled experiment.
@@ -19,9 +25,11 @@ pub trait Scientist<'a, R: Clone + PartialEq + Serialize> {
     fn conduct<F>(name: &'static str, experiment_block: F) -> VictorsResult<R>
     where
         F: Fn(&mut Experiment<'_, R>) -> VictorsResult<()>,
+        R: PartialEq,
     {
         let mut experiment = Experiment::new(name);
         experiment.result_publisher(Self::get_publisher());
+        experiment.err_on_mismatches(Self::err_on_mismatches());
         experiment_block(&mut experiment)?;
         return experiment.run();
     }
@@ -43,128 +51,138 @@ pub trait Scientist<'a, R: Clone + PartialEq + Serialize> {
     ) -> VictorsResult<R>
         where
             F: Fn(&mut UncontrolledExperiment<'_, R>) -> VictorsResult<()>,
+            R: PartialEq,
     {
         let mut experiment = UncontrolledExperiment::new(name);
         experiment.result_publisher(Self::get_publisher());
+        experiment.err_on_mismatches(Self::err_on_mismatches());
         experiment_block(&mut experiment)?;
         return experiment.run(return_candidate_result);
     }
 
+    /// Define and run a controlled experiment whose behaviors are asynchronous.
+    ///
+    /// # Arguments
+    /// * `name` - the name of the experiment
+    /// * `experiment_block` - Function to configure the experiment
+    ///
+    /// # Return
+    /// Returns a future resolving to the calculated value of the control experiment or error
+    fn conduct_async<F>(
+        name: &'static str,
+        experiment_block: F,
+    ) -> Pin<Box<dyn Future<Output = VictorsResult<R>> + 'a>>
+        where
+            F: FnOnce(&mut AsyncExperiment<'a, R>) -> VictorsResult<()>,
+            R: PartialEq + 'a,
+    {
+        let mut experiment = AsyncExperiment::new(name);
+        experiment.result_publisher(Self::get_publisher());
+        experiment.err_on_mismatches(Self::err_on_mismatches());
+        let configured = experiment_block(&mut experiment);
+        return Box::pin(async move {
+            configured?;
+            experiment.run().await
+        });
+    }
+
     fn get_publisher() -> Self::P;
 
-    // TODO: should have err_on_mismatch fn?
+    /// Whether experiments conducted by this scientist return an error when the control and
+    /// candidates mismatch, unless the experiment block says otherwise.
+    ///
+    /// Defaults to the process wide setting, see [set_err_on_mismatches].
+    fn err_on_mismatches() -> bool {
+        return err_on_mismatches();
+    }
 }
 
 pub struct Victor;
 
-impl<'a, R: Clone + PartialEq + Serialize> Scientist<'a, R> for Victor {
-    type P = NoopPublisher;
+impl<'a, R: Serialize> Scientist<'a, R> for Victor {
+    type P = GlobalResultPublisher;
 
     fn get_publisher() -> Self::P {
-        return NoopPublisher{};
+        return publisher();
     }
 }
 
-// /// Represents the globally configured [`Publisher`] instance for this application.
-// #[derive(Clone)]
-// pub struct GlobalResultPublisher<R: Clone + PartialEq> {
-//     publisher: Arc<dyn Publisher<R>>,
-// }
-// impl<R: Clone + PartialEq> GlobalResultPublisher<R> {
-//     /// Create a new GlobalResultPublisher instance from a struct that implements `Publisher`.
-//     fn new<P>(publisher: P) -> Self
-//         where
-//             P: Publisher<R> + 'static,
-//     {
-//         GlobalResultPublisher {
-//             publisher: Arc::new(publisher),
-//         }
-//     }
-// }
-
-// static GLOBAL_RESULT_PUBLISHER: once_cell::sync::Lazy<RwLock<GlobalResultPublisher<R>>> = once_cell::sync::Lazy::new(|| {
-//     RwLock::new(GlobalResultPublisher::new(
-//         NoopPublisher{},
-//     ))
-// });
-
-pub trait ObjectSafePublisher {
+/// Represents the globally configured [`Publisher`] instance for this application.
+///
+/// Experiments of any return type can publish to it. Their results are erased into
+/// [`ScientistValue`]s before being handed to the installed publisher, see [`ExperimentResult::erase`].
+#[derive(Clone)]
+pub struct GlobalResultPublisher {
+    // None until a publisher is installed, which saves converting results nobody listens to
+    publisher: Option<Arc<dyn Publisher<ScientistValue> + Send + Sync>>,
 }
 
-impl<T> ObjectSafePublisher for T
-where
-    T: Clone + PartialEq + Serialize
-{
+impl GlobalResultPublisher {
+    /// Create a new GlobalResultPublisher instance from a struct that implements `Publisher`.
+    fn new<P>(publisher: P) -> Self
+        where
+            P: Publisher<ScientistValue> + Send + Sync + 'static,
+    {
+        GlobalResultPublisher {
+            publisher: Some(Arc::new(publisher)),
+        }
+    }
 
+    fn noop() -> Self {
+        GlobalResultPublisher { publisher: None }
+    }
 }
 
-pub struct BoxedResultPublisher(Box<dyn Publisher<_> + Clone + PartialEq + Serialize>);
-impl BoxedResultPublisher {
-    pub(crate) fn new<T>(publisher: T) -> Self
-        where
-            T: Clone + PartialEq + Serialize + 'static,
-    {
-        BoxedResultPublisher(Box::new(publisher))
+impl<R: Serialize> Publisher<R> for GlobalResultPublisher {
+    fn publish(&self, result: &ExperimentResult<R>) -> Result<(), PublishError> {
+        return match &self.publisher {
+            Some(publisher) => publisher.publish(&result.erase()),
+            None => Ok(()),
+        };
     }
 }
 
+static GLOBAL_RESULT_PUBLISHER: Lazy<RwLock<GlobalResultPublisher>> =
+    Lazy::new(|| RwLock::new(GlobalResultPublisher::noop()));
+
+/// Returns an instance of the currently configured global [`Publisher`] through
+/// [`GlobalResultPublisher`].
+///
+/// New experiments publish to it unless given a publisher of their own.
+pub fn publisher() -> GlobalResultPublisher {
+    GLOBAL_RESULT_PUBLISHER
+        .read()
+        .expect("GLOBAL_RESULT_PUBLISHER RwLock poisoned")
+        .clone()
+}
 
-/// Represents the globally configured [`Publisher`] instance for this application.
-#[derive(Clone)]
-pub struct GlobalResultPublisherProvider {
-    publisher: Arc<BoxedResultPublisher>,
+/// Sets the given [`Publisher`] instance as the current global publisher.
+///
+/// It returns the [`GlobalResultPublisher`] that was previously installed, which publishes nothing
+/// if a publisher had not been set before. Experiments created before the call keep publishing to
+/// the previous publisher.
+pub fn set_publisher<P>(new_publisher: P) -> GlobalResultPublisher
+    where
+        P: Publisher<ScientistValue> + Send + Sync + 'static,
+{
+    let mut publisher = GLOBAL_RESULT_PUBLISHER
+        .write()
+        .expect("GLOBAL_RESULT_PUBLISHER RwLock poisoned");
+    mem::replace(&mut *publisher, GlobalResultPublisher::new(new_publisher))
 }
 
-impl GlobalResultPublisherProvider {
-    /// Create a new GlobalResultPublisher instance from a struct that implements `Publisher`.
-    fn new<R, P>(publisher: P) -> Self
-        where
-            R: Clone + PartialEq + Serialize,
-            P: Publisher<R> + 'static,
-    {
-        GlobalResultPublisher {
-            publisher: Arc::new(publisher),
-        }
-    }
+static ERR_ON_MISMATCHES: AtomicBool = AtomicBool::new(false);
+
+/// Returns whether new experiments return an error when the control and candidates mismatch.
+pub fn err_on_mismatches() -> bool {
+    return ERR_ON_MISMATCHES.load(Ordering::Relaxed);
 }
 
-static GLOBAL_RESULT_PUBLISHER: once_cell::sync::Lazy<RwLock<GlobalResultPublisher>> = once_cell::sync::Lazy::new(|| {
-    RwLock::new(GlobalResultPublisher::new(
-        NoopPublisher{},
-    ))
-});
-
-
-
-// /// Returns an instance of the currently configured global [`TracerProvider`] through
-// /// [`GlobalTracerProvider`].
-// ///
-// /// [`TracerProvider`]: crate::trace::TracerProvider
-// /// [`GlobalTracerProvider`]: crate::global::GlobalTracerProvider
-// pub fn tracer_provider() -> GlobalTracerProvider {
-//     GLOBAL_TRACER_PROVIDER
-//         .read()
-//         .expect("GLOBAL_TRACER_PROVIDER RwLock poisoned")
-//         .clone()
-// }
-
-// /// Sets the given [`TracerProvider`] instance as the current global provider.
-// ///
-// /// It returns the [`TracerProvider`] instance that was previously mounted as global provider
-// /// (e.g. [`NoopTracerProvider`] if a provider had not been set before).
-// ///
-// /// [`TracerProvider`]: crate::trace::TracerProvider
-// pub fn set_tracer_provider<P, T, S>(new_provider: P) -> GlobalTracerProvider
-//     where
-//         S: trace::Span + Send + Sync + 'static,
-//         T: trace::Tracer<Span = S> + Send + Sync + 'static,
-//         P: trace::TracerProvider<Tracer = T> + Send + Sync + 'static,
-// {
-//     let mut tracer_provider = GLOBAL_TRACER_PROVIDER
-//         .write()
-//         .expect("GLOBAL_TRACER_PROVIDER RwLock poisoned");
-//     mem::replace(
-//         &mut *tracer_provider,
-//         GlobalTracerProvider::new(new_provider),
-//     )
-// }
+/// Sets whether new experiments return an error when the control and candidates mismatch.
+///
+/// Off by default. Turn it on in test suites to make every mismatch fail the test, while
+/// production keeps reporting mismatches only through publishers. Experiments created before the
+/// call, and experiments setting [Experiment::err_on_mismatches] themselves, are unaffected.
+pub fn set_err_on_mismatches(err_on_mismatches: bool) {
+    ERR_ON_MISMATCHES.store(err_on_mismatches, Ordering::Relaxed);
+}
@@ -0,0 +1,23 @@
+// Lives in its own test binary because it changes process wide state, which would race the
+// experiments of the unit tests running in parallel.
+
+use victors::{errors::VictorsErrors, victor, Experiment};
+
+#[test]
+fn should_err_on_mismatches_of_new_experiments_when_enabled_process_wide() {
+    let mut before: Experiment<'_, i32> = Experiment::new("before");
+    before.control(|| 1).unwrap();
+    before.candidate(|| 2).unwrap();
+
+    victor::set_err_on_mismatches(true);
+    let mut strict: Experiment<'_, i32> = Experiment::new("strict");
+    strict.control(|| 1).unwrap();
+    strict.candidate(|| 2).unwrap();
+    let strict_result = strict.run();
+    let before_result = before.run();
+    victor::set_err_on_mismatches(false);
+
+    assert!(matches!(strict_result, Err(VictorsErrors::MismatchError(_))));
+    assert_eq!(1, before_result.unwrap());
+    assert!(!victor::err_on_mismatches());
+}